@@ -0,0 +1,97 @@
+/// Index pairs into `a` and `b` of a longest common subsequence.
+pub fn common<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the lcs length of a[i..] and b[j..]
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Marks words removed from `old` as `[-...-]` and words added in `new` as `{+...+}`.
+pub fn words(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+    let mut out: Vec<String> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = common(&old, &new);
+    // a sentinel pair flushes whatever trails the last common word
+    pairs.push((old.len(), new.len()));
+    for (next_i, next_j) in pairs {
+        if i < next_i {
+            out.push(format!("[-{}-]", old[i..next_i].join(" ")));
+        }
+        if j < next_j {
+            out.push(format!("{{+{}+}}", new[j..next_j].join(" ")));
+        }
+        if next_j < new.len() {
+            out.push(new[next_j].to_owned());
+        }
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+    out.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_of_empty_sequences() {
+        let empty: [&str; 0] = [];
+        assert!(common(&empty, &empty).is_empty());
+        assert!(common(&empty, &["a"]).is_empty());
+        assert!(common(&["a"], &empty).is_empty());
+    }
+
+    #[test]
+    fn common_skips_moved_items() {
+        // c moved to the front, so only a and b keep their order
+        assert_eq!(
+            common(&["a", "b", "c"], &["c", "a", "b"]),
+            vec![(0, 1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn common_pairs_duplicates_once() {
+        assert_eq!(
+            common(&["a", "a", "b"], &["a", "b", "a"]),
+            vec![(0, 0), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn words_marks_changes() {
+        assert_eq!(words("a b c", "a x c"), "a [-b-] {+x+} c");
+        assert_eq!(words("a b", "a b c"), "a b {+c+}");
+        assert_eq!(words("a b c", "b c"), "[-a-] b c");
+    }
+
+    #[test]
+    fn words_of_empty_text() {
+        assert_eq!(words("", ""), "");
+        assert_eq!(words("", "new text"), "{+new text+}");
+        assert_eq!(words("old text", ""), "[-old text-]");
+    }
+}
@@ -12,6 +12,8 @@ use std::io::{stdout, Write};
 pub enum ExEventStatus {
     Consumed,
     Run(String),
+    // tab was pressed, so the caller should offer completions for the buffer
+    Complete(String),
     None,
 }
 
@@ -22,6 +24,15 @@ pub struct Ex {
     cursor_pos: u16,
     pub history: Vec<String>,
     hindex: Option<usize>,
+    completions: Vec<String>,
+    // where the completed word starts and which completion is shown
+    completion: Option<(usize, usize)>,
+}
+
+impl Default for Ex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Ex {
@@ -33,6 +44,8 @@ impl Ex {
             cursor_pos: 0,
             history: Vec::new(),
             hindex: None,
+            completions: Vec::new(),
+            completion: None,
         }
     }
 
@@ -47,6 +60,25 @@ impl Ex {
         self.display(row)
     }
 
+    /// Replaces the buffer from `start` on with the first of the completions,
+    /// cycling through the rest on each further tab.
+    pub fn complete(&mut self, start: usize, completions: Vec<String>) {
+        if completions.is_empty() {
+            return;
+        }
+        self.completions = completions;
+        self.completion = Some((start, 0));
+        self.show_completion();
+    }
+
+    fn show_completion(&mut self) {
+        if let Some((start, index)) = self.completion {
+            self.buffer.truncate(start);
+            self.buffer.push_str(&self.completions[index]);
+            self.cursor_pos = self.buffer.len() as u16;
+        }
+    }
+
     pub fn display(&mut self, row: u16) -> Result<(), Error> {
         let mut stdout = stdout();
         stdout
@@ -76,6 +108,18 @@ impl Ex {
             }
             return ExEventStatus::None;
         }
+        if event.code == KeyCode::Tab {
+            if let Some(hindex) = self.hindex.take() {
+                self.buffer = self.history[hindex].clone();
+            }
+            if let Some((start, index)) = self.completion {
+                self.completion = Some((start, (index + 1) % self.completions.len()));
+                self.show_completion();
+                return ExEventStatus::Consumed;
+            }
+            return ExEventStatus::Complete(self.buffer.clone());
+        }
+        self.completion = None;
         match event.code {
             KeyCode::Esc => {
                 self.active = false;
@@ -88,10 +132,10 @@ impl Ex {
                         self.history.remove(hindex)
                     }
                 };
-                if command.len() > 0 {
+                if !command.is_empty() {
                     self.active = false;
                     self.cursor_pos = 0;
-                    if self.history.len() == 0 || self.history[self.history.len() - 1] != command {
+                    if self.history.is_empty() || self.history[self.history.len() - 1] != command {
                         self.history.push(command.clone());
                     }
                     return ExEventStatus::Run(command);
@@ -103,19 +147,19 @@ impl Ex {
             KeyCode::End => {
                 self.cursor_pos = max(self.buffer.len() as i16 - 1, 0) as u16;
             }
-            KeyCode::Up if self.buffer.len() == 0 => match self.hindex {
+            KeyCode::Up if self.buffer.is_empty() => match self.hindex {
                 Some(hindex) => {
                     self.hindex = Some(max(hindex as isize - 1, 0) as usize);
                     self.cursor_pos = self.history[self.hindex.unwrap()].len() as u16;
                 }
-                None if self.history.len() > 0 => {
+                None if !self.history.is_empty() => {
                     self.hindex = Some(self.history.len() - 1);
                     self.cursor_pos = self.history[self.hindex.unwrap()].len() as u16;
                 }
                 _ => {}
             },
-            KeyCode::Down => match self.hindex {
-                Some(hindex) => {
+            KeyCode::Down => {
+                if let Some(hindex) = self.hindex {
                     let next_index = hindex + 1;
                     if next_index >= self.history.len() {
                         self.hindex = None;
@@ -125,8 +169,7 @@ impl Ex {
                         self.cursor_pos = self.history[self.hindex.unwrap()].len() as u16;
                     }
                 }
-                _ => {}
-            },
+            }
             KeyCode::Left => {
                 self.cursor_pos = max(self.cursor_pos as i16 - 1, 0) as u16;
             }
@@ -141,7 +184,7 @@ impl Ex {
                     self.buffer = self.history[hindex].clone();
                     self.hindex = None;
                 }
-                if self.buffer.len() > 0 {
+                if !self.buffer.is_empty() {
                     let new_cursor_pos = max(self.cursor_pos as i16 - 1, 0) as u16;
                     let before = &self.buffer[0..new_cursor_pos as usize];
                     let after = &self.buffer[self.cursor_pos as usize..self.buffer.len()];
@@ -161,6 +204,6 @@ impl Ex {
             }
             _ => return ExEventStatus::None,
         }
-        return ExEventStatus::Consumed;
+        ExEventStatus::Consumed
     }
 }
@@ -0,0 +1,314 @@
+use crossterm::style::Color;
+use std::ops::Range;
+
+/// What terki knows about highlighting a language.
+pub struct Syntax {
+    pub name: &'static str,
+    // names a language hint might use for it
+    aliases: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static str,
+    // snippets that suggest code is in this language
+    markers: &'static [&'static str],
+}
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        name: "rust",
+        aliases: &["rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "else", "enum", "false",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+            "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"",
+        markers: &["fn ", "let mut ", "impl ", "::", "pub fn", "-> ", "&mut "],
+    },
+    Syntax {
+        name: "python",
+        aliases: &["py", "python3"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+            "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return",
+            "True", "try", "while", "with", "yield",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: "\"'",
+        markers: &["def ", "import ", "self.", "elif ", "print(", "__init__"],
+    },
+    Syntax {
+        name: "javascript",
+        aliases: &["js", "node", "typescript", "ts"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "let",
+            "new",
+            "null",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'`",
+        markers: &[
+            "function", "const ", "var ", "=> ", "===", "console.", "require(",
+        ],
+    },
+    Syntax {
+        name: "coffeescript",
+        aliases: &["coffee"],
+        keywords: &[
+            "and",
+            "break",
+            "by",
+            "catch",
+            "class",
+            "continue",
+            "else",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "if",
+            "in",
+            "is",
+            "isnt",
+            "loop",
+            "new",
+            "no",
+            "not",
+            "null",
+            "of",
+            "off",
+            "on",
+            "or",
+            "return",
+            "switch",
+            "then",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "undefined",
+            "unless",
+            "until",
+            "when",
+            "while",
+            "yes",
+        ],
+        line_comments: &["#"],
+        block_comment: Some(("###", "###")),
+        quotes: "\"'",
+        markers: &[" -> ", " => ", "@", " unless ", " isnt ", " then "],
+    },
+    Syntax {
+        name: "shell",
+        aliases: &["sh", "bash", "zsh", "console"],
+        keywords: &[
+            "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for",
+            "function", "if", "in", "local", "then", "until", "while",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: "\"'",
+        markers: &["$ ", "echo ", "fi\n", "done\n", "sudo ", "${", "| grep"],
+    },
+    Syntax {
+        name: "c",
+        aliases: &["c++", "cpp", "h", "java", "go", "csharp", "cs"],
+        keywords: &[
+            "break", "case", "char", "class", "const", "continue", "default", "do", "double",
+            "else", "enum", "extern", "false", "float", "for", "func", "if", "import", "int",
+            "long", "new", "package", "private", "public", "return", "short", "static", "struct",
+            "switch", "true", "typedef", "unsigned", "void", "while",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        markers: &[
+            "#include",
+            "int main",
+            "printf(",
+            "void ",
+            "public static",
+            "func ",
+        ],
+    },
+    Syntax {
+        name: "json",
+        aliases: &[],
+        keywords: &["true", "false", "null"],
+        line_comments: &[],
+        block_comment: None,
+        quotes: "\"",
+        markers: &[],
+    },
+    Syntax {
+        name: "sql",
+        aliases: &["postgres", "mysql", "sqlite"],
+        keywords: &[
+            "and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join",
+            "not", "null", "on", "or", "order", "select", "set", "table", "update", "values",
+            "where", "AND", "AS", "BY", "CREATE", "DELETE", "FROM", "GROUP", "INSERT", "INTO",
+            "JOIN", "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE",
+            "VALUES", "WHERE",
+        ],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: "'\"",
+        markers: &[
+            "SELECT ",
+            "select ",
+            " FROM ",
+            " from ",
+            "WHERE ",
+            "INSERT INTO",
+        ],
+    },
+];
+
+/// The syntax named by a hint, or else the one the code looks most like.
+pub fn syntax(hint: Option<&str>, code: &str) -> Option<&'static Syntax> {
+    if let Some(hint) = hint {
+        let hint = hint.trim().to_lowercase();
+        let named = SYNTAXES
+            .iter()
+            .find(|syntax| syntax.name == hint || syntax.aliases.contains(&hint.as_str()));
+        if named.is_some() {
+            return named;
+        }
+    }
+    // a #! line names its interpreter
+    if let Some(shebang) = code.lines().next().and_then(|line| line.strip_prefix("#!")) {
+        let interpreter = shebang.rsplit(['/', ' ']).next().unwrap_or("");
+        return syntax(Some(interpreter.trim_end_matches(char::is_numeric)), "");
+    }
+    let trimmed = code.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(code).is_ok()
+    {
+        return syntax(Some("json"), "");
+    }
+    SYNTAXES
+        .iter()
+        .map(|syntax| {
+            let score: usize = syntax
+                .markers
+                .iter()
+                .map(|marker| code.matches(marker).count())
+                .sum();
+            (score, syntax)
+        })
+        .filter(|(score, _)| *score > 0)
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, syntax)| syntax)
+}
+
+/// Colors for the pieces of one line of code.
+///
+/// `in_comment` carries an unfinished block comment over to the next line.
+pub fn highlight(line: &str, syntax: &Syntax, in_comment: &mut bool) -> Vec<(Range<usize>, Color)> {
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        if *in_comment {
+            let (_, close) = syntax.block_comment.unwrap();
+            let end = match rest.find(close) {
+                Some(end) => {
+                    *in_comment = false;
+                    i + end + close.len()
+                }
+                None => line.len(),
+            };
+            pieces.push((i..end, Color::DarkGrey));
+            i = end;
+            continue;
+        }
+        if let Some((open, _)) = syntax.block_comment {
+            if rest.starts_with(open) {
+                *in_comment = true;
+                pieces.push((i..i + open.len(), Color::DarkGrey));
+                i += open.len();
+                continue;
+            }
+        }
+        if syntax
+            .line_comments
+            .iter()
+            .any(|start| rest.starts_with(start))
+        {
+            pieces.push((i..line.len(), Color::DarkGrey));
+            break;
+        }
+        let c = rest.chars().next().unwrap();
+        if syntax.quotes.contains(c) {
+            // strings end at the matching quote that isn't escaped
+            let mut end = line.len();
+            let mut escaped = false;
+            for (offset, next) in rest.char_indices().skip(1) {
+                if next == c && !escaped {
+                    end = i + offset + 1;
+                    break;
+                }
+                escaped = next == '\\' && !escaped;
+            }
+            pieces.push((i..end, Color::Green));
+            i = end;
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..length];
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                pieces.push((i..i + length, Color::Yellow));
+            } else if syntax.keywords.contains(&word) {
+                pieces.push((i..i + length, Color::Magenta));
+            }
+            i += length;
+            continue;
+        }
+        i += c.len_utf8();
+    }
+    pieces
+}
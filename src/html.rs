@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+// the text an entity like `&amp;` or `&#39;` stands for
+fn entity(name: &str) -> Option<String> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "copy" => '©',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number
+                .strip_prefix('x')
+                .or_else(|| number.strip_prefix('X'))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            std::char::from_u32(code)?
+        }
+    };
+    Some(c.to_string())
+}
+
+fn decode(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest
+            .find(';')
+            .filter(|semi| *semi < 10)
+            .and_then(|semi| Some((entity(&rest[1..semi])?, semi)));
+        match decoded {
+            Some((text, semi)) => {
+                out.push_str(&text);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    attributes: HashMap<String, String>,
+}
+
+fn parse_tag(tag: &str) -> Tag {
+    let closing = tag.starts_with('/');
+    let tag = tag.trim_start_matches('/').trim_end_matches('/');
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let mut attributes = HashMap::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_lowercase();
+        rest = rest[name_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                    (&after[1..end], after.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode(raw);
+            rest = remaining.trim_start();
+        }
+        attributes.insert(name, value);
+    }
+    Tag {
+        name: tag[..name_end].to_lowercase(),
+        closing,
+        attributes,
+    }
+}
+
+// backslashes anything markdown would read as markup, keeping [[Links]] working
+fn escape(text: &str, out: &mut String) {
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with("[[") {
+            if let Some(close) = rest.find("]]") {
+                out.push_str(&rest[..close + 2]);
+                rest = &rest[close + 2..];
+                continue;
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        if "\\`*_[]|".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+/// Rewrites an html item as markdown, so it can be drawn the same way.
+///
+/// Only the markup that reads well in a terminal is kept; anything else
+/// is dropped, leaving just its text.
+pub fn to_markdown(html: &str) -> String {
+    let mut converter = Converter::default();
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let is_tag = rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        if is_tag {
+            let end = match rest.find('>') {
+                Some(end) => end,
+                // a tag that's never closed is just text
+                None => {
+                    converter.text(&decode(rest));
+                    break;
+                }
+            };
+            let tag = parse_tag(&rest[1..end]);
+            rest = &rest[end + 1..];
+            // scripts and styles are never shown
+            if !tag.closing && (tag.name == "script" || tag.name == "style") {
+                let close = format!("</{}", tag.name);
+                let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                rest = &rest[end..];
+                continue;
+            }
+            converter.tag(tag);
+            continue;
+        }
+        // a stray < is text too, up to wherever the next tag might start
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '<')
+            .map_or(rest.len(), |(end, _)| end);
+        converter.text(&decode(&rest[..end]));
+        rest = &rest[end..];
+    }
+    converter.flush();
+    converter.out
+}
+
+#[derive(Default)]
+struct Converter {
+    out: String,
+    // the block being built and what starts its line
+    line: String,
+    prefix: String,
+    // the numbering of each open list, none for bullets
+    lists: Vec<Option<usize>>,
+    quotes: usize,
+    links: Vec<String>,
+    pre: Option<String>,
+    code: bool,
+    table: Vec<Vec<String>>,
+    table_header: bool,
+    cell: Option<String>,
+}
+
+impl Converter {
+    fn flush(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let line = line.trim();
+        // an empty block keeps its prefix for the text that follows
+        if line.is_empty() {
+            return;
+        }
+        let prefix = std::mem::take(&mut self.prefix);
+        let quote = "> ".repeat(self.quotes);
+        self.out
+            .push_str(&format!("{}{}{}\n\n", quote, prefix, line));
+    }
+
+    // closing tags for cells are optional, so cells also end when the next one starts
+    fn end_cell(&mut self) {
+        if let Some(cell) = self.cell.take() {
+            if self.table.is_empty() {
+                self.table.push(Vec::new());
+            }
+            self.table.last_mut().unwrap().push(cell.trim().to_owned());
+        }
+    }
+
+    // where inline text goes, a table cell or the current block
+    fn target(&mut self) -> &mut String {
+        match &mut self.cell {
+            Some(cell) => cell,
+            None => &mut self.line,
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(pre) = &mut self.pre {
+            pre.push_str(text);
+            return;
+        }
+        let collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let mut shown = String::new();
+        if text.starts_with(char::is_whitespace) && !self.target().ends_with(' ') {
+            shown.push(' ');
+        }
+        if self.code {
+            shown.push_str(&collapsed.replace('`', "'"));
+        } else {
+            escape(&collapsed, &mut shown);
+        }
+        if !collapsed.is_empty() && text.ends_with(char::is_whitespace) {
+            shown.push(' ');
+        }
+        self.target().push_str(&shown);
+    }
+
+    fn tag(&mut self, tag: Tag) {
+        if self.pre.is_some() && !(tag.closing && tag.name == "pre") {
+            return;
+        }
+        let name = tag.name.as_str();
+        match (name, tag.closing) {
+            ("p", _) | ("div", _) | ("br", _) | ("section", _) => self.flush(),
+            ("h1", false)
+            | ("h2", false)
+            | ("h3", false)
+            | ("h4", false)
+            | ("h5", false)
+            | ("h6", false) => {
+                self.flush();
+                let level = name[1..].parse().unwrap_or(1);
+                self.prefix = format!("{} ", "#".repeat(level));
+            }
+            ("h1", true)
+            | ("h2", true)
+            | ("h3", true)
+            | ("h4", true)
+            | ("h5", true)
+            | ("h6", true) => self.flush(),
+            ("ul", false) => {
+                self.flush();
+                self.lists.push(None);
+            }
+            ("ol", false) => {
+                self.flush();
+                self.lists.push(Some(0));
+            }
+            ("ul", true) | ("ol", true) => {
+                self.flush();
+                self.lists.pop();
+            }
+            ("li", false) => {
+                self.flush();
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", number)
+                    }
+                    _ => "-".to_string(),
+                };
+                self.prefix = format!("{}{} ", "  ".repeat(depth), marker);
+            }
+            ("li", true) => self.flush(),
+            ("blockquote", false) => {
+                self.flush();
+                self.quotes += 1;
+            }
+            ("blockquote", true) => {
+                self.flush();
+                self.quotes = self.quotes.saturating_sub(1);
+            }
+            ("hr", _) => {
+                self.flush();
+                self.out.push_str("---\n\n");
+            }
+            ("pre", false) => {
+                self.flush();
+                self.pre = Some(String::new());
+            }
+            ("pre", true) => {
+                if let Some(pre) = self.pre.take() {
+                    let pre = pre.trim_matches('\n');
+                    self.out.push_str(&format!("```\n{}\n```\n\n", pre));
+                }
+            }
+            ("b", _) | ("strong", _) => self.target().push_str("**"),
+            ("i", _) | ("em", _) => self.target().push('*'),
+            ("code", closing) => {
+                self.code = !closing;
+                self.target().push('`');
+            }
+            ("a", false) => {
+                let href = tag.attributes.get("href").cloned().unwrap_or_default();
+                self.links.push(href);
+                self.target().push('[');
+            }
+            ("a", true) => {
+                if let Some(href) = self.links.pop() {
+                    let markdown = format!("]({})", href.replace(')', "%29"));
+                    self.target().push_str(&markdown);
+                }
+            }
+            ("img", false) => {
+                let alt = tag.attributes.get("alt").cloned().unwrap_or_default();
+                let mut placeholder = String::from("*\\[image");
+                if !alt.trim().is_empty() {
+                    placeholder.push_str(": ");
+                    escape(alt.trim(), &mut placeholder);
+                }
+                placeholder.push_str("\\]* ");
+                self.target().push_str(&placeholder);
+            }
+            ("table", false) => {
+                self.flush();
+                self.table.clear();
+                self.table_header = false;
+            }
+            ("tr", false) => {
+                self.end_cell();
+                self.table.push(Vec::new());
+            }
+            ("td", false) | ("th", false) => {
+                self.end_cell();
+                if name == "th" && self.table.len() <= 1 {
+                    self.table_header = true;
+                }
+                self.cell = Some(String::new());
+            }
+            ("td", true) | ("th", true) => self.end_cell(),
+            ("table", true) => {
+                self.end_cell();
+                let rows = std::mem::take(&mut self.table);
+                for (i, row) in rows.iter().filter(|row| !row.is_empty()).enumerate() {
+                    self.out.push_str(&format!("| {} |\n", row.join(" | ")));
+                    if i == 0 && self.table_header {
+                        let separator = vec!["---"; row.len()].join(" | ");
+                        self.out.push_str(&format!("| {} |\n", separator));
+                    }
+                }
+                self.out.push('\n');
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_tags_are_text() {
+        assert_eq!(to_markdown("x <b café"), "x <b café\n\n");
+        assert_eq!(to_markdown("<"), "<\n\n");
+    }
+
+    #[test]
+    fn stray_brackets_keep_later_tags() {
+        assert_eq!(to_markdown("a < b <b>c</b>"), "a < b **c**\n\n");
+    }
+
+    #[test]
+    fn nested_inline_tags() {
+        assert_eq!(
+            to_markdown("<p><b>bold <i>both</i></b> <a href=\"x\">link</a></p>"),
+            "**bold *both*** [link](x)\n\n"
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        let html = "<ul><li>one<ol><li>two</li><li>three</li></ol></li><li>four</li></ul>";
+        assert_eq!(
+            to_markdown(html),
+            "- one\n\n  1. two\n\n  2. three\n\n- four\n\n"
+        );
+    }
+
+    #[test]
+    fn scripts_and_comments_are_dropped() {
+        assert_eq!(
+            to_markdown("a<!-- note --><script>alert('<b>')</script>b"),
+            "ab\n\n"
+        );
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            to_markdown("&lt;tag&gt; &amp; &#233;&#x41; &bogus;"),
+            "<tag> & éA &bogus;\n\n"
+        );
+    }
+}
@@ -1,9 +1,20 @@
+mod diff;
 mod ex;
+mod highlight;
+mod html;
+mod lineup;
+mod markdown;
+mod media;
+mod neighborhood;
 mod pane;
+mod render;
 mod terki;
 mod wiki;
 
 pub use ex::{Ex, ExEventStatus};
+pub use lineup::{Lineup, Visit, WikiPane};
+pub use media::InlineImage;
+pub use neighborhood::{Neighbor, Neighborhood, SitemapEntry};
 pub use pane::Pane;
 pub use terki::{Location, Terki};
-pub use wiki::{DisplayLine, Page, PageStore, Wiki};
+pub use wiki::{DisplayLine, LinkTarget, NotFound, Page, PageStore, Rendering, Span, Wiki};
@@ -0,0 +1,105 @@
+use crate::Pane;
+
+/// A page a pane has shown, and where it was scrolled to.
+#[derive(Clone)]
+pub struct Visit {
+    pub wiki: String,
+    pub slug: String,
+    pub scroll_index: usize,
+    pub highlight_index: Option<usize>,
+    // the width the page was wrapped to, which the scroll index depends on
+    pub width: usize,
+}
+
+/// A page open in a lineup, and the pane it's shown in.
+pub struct WikiPane {
+    pub wiki: String,
+    pub slug: String,
+    pub pane: Pane,
+    // the pages shown in this pane before and after this one
+    pub back: Vec<Visit>,
+    pub forward: Vec<Visit>,
+}
+
+impl WikiPane {
+    pub fn new(wiki: &str, slug: &str, pane: Pane) -> WikiPane {
+        WikiPane {
+            wiki: wiki.to_owned(),
+            slug: slug.to_owned(),
+            pane,
+            back: Vec::new(),
+            forward: Vec::new(),
+        }
+    }
+
+    pub fn visit(&self) -> Visit {
+        Visit {
+            wiki: self.wiki.clone(),
+            slug: self.slug.clone(),
+            scroll_index: self.pane.scroll_index,
+            highlight_index: self.pane.highlight_index,
+            width: self.pane.width(),
+        }
+    }
+
+    /// Takes over the history of the pane this one replaces, so its page can be gone back to.
+    pub fn replaces(&mut self, old: WikiPane) {
+        let visit = old.visit();
+        self.back = old.back;
+        // reloading a page isn't going anywhere new
+        if visit.wiki == self.wiki && visit.slug == self.slug {
+            self.forward = old.forward;
+        } else {
+            self.back.push(visit);
+        }
+    }
+}
+
+/// Pages opened one from another, read left to right like the web client's lineup.
+pub struct Lineup {
+    pub name: String,
+    pub panes: Vec<WikiPane>,
+    pub active: usize,
+    // the leftmost pane on screen
+    pub first: usize,
+}
+
+impl Lineup {
+    pub fn new(name: &str) -> Lineup {
+        Lineup {
+            name: name.to_owned(),
+            panes: Vec::new(),
+            active: 0,
+            first: 0,
+        }
+    }
+
+    pub fn active(&self) -> &WikiPane {
+        &self.panes[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut WikiPane {
+        &mut self.panes[self.active]
+    }
+
+    /// Closes the active pane, unless it's the only one left.
+    pub fn close(&mut self) -> bool {
+        if self.panes.len() < 2 {
+            return false;
+        }
+        self.panes.remove(self.active);
+        self.active = self.active.min(self.panes.len() - 1);
+        true
+    }
+
+    /// Moves along the lineup only as far as it takes to show the active pane,
+    /// when `columns` panes fit on screen.
+    pub fn scroll_to_active(&mut self, columns: usize) {
+        if self.active < self.first {
+            self.first = self.active;
+        } else if self.active >= self.first + columns {
+            self.first = self.active + 1 - columns;
+        }
+        self.first = self.first.min(self.panes.len().saturating_sub(columns));
+    }
+}
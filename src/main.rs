@@ -6,16 +6,14 @@ use crossterm::{
     execute,
     terminal::{
         disable_raw_mode, enable_raw_mode, size, EnterAlternateScreen, LeaveAlternateScreen,
-        SetSize,
     },
 };
-use dirs;
 use std::io::{stdout, Write};
 use terki::{Location, Terki};
-use tokio;
 
 async fn run(terki: &mut Terki, wiki: Option<&str>) -> Result<(), Error> {
-    if let Some(wiki) = wiki {
+    // a wiki the restored lineup already shows is left where it was
+    if let Some(wiki) = wiki.filter(|wiki| !terki.shows(wiki)) {
         terki
             .display(wiki, "welcome-visitors", Location::End)
             .await?;
@@ -34,7 +32,6 @@ async fn main() -> Result<(), Error> {
         .get_matches();
     let size = size()?;
     let mut terki = Terki::new((size.0 as usize, size.1 as usize));
-    terki.load().await?;
     let wiki = if let Some(path) = matches.value_of("local") {
         let mut wikidir = dirs::home_dir()
             .expect("unable to get home dir")
@@ -54,22 +51,20 @@ async fn main() -> Result<(), Error> {
         Some(path.to_owned())
     } else if let Some(url) = matches.value_of("url") {
         Some(terki.add_remote(url)?)
-    } else if terki.wikis.len() == 0 {
-        println!("Must pass in at least one of: --url or --local");
-        std::process::exit(1);
     } else {
         None
     };
+    // loaded after adding wikis, so lineups with pages on a local wiki can be restored
+    terki.load().await?;
+    if terki.wikis.is_empty() {
+        println!("Must pass in at least one of: --url or --local");
+        std::process::exit(1);
+    }
 
     enable_raw_mode()?;
     let mut stdout = stdout();
     println!("{}, {}", size.0, size.1);
-    execute!(
-        stdout,
-        EnterAlternateScreen,
-        SetSize(size.0, size.1 + 1000),
-        EnableMouseCapture
-    )?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let result = run(&mut terki, wiki.as_deref()).await;
     execute!(stdout, LeaveAlternateScreen, DisableMouseCapture)?;
     disable_raw_mode()?;
@@ -0,0 +1,471 @@
+use crate::render::Link;
+use std::ops::Range;
+
+/// A block of a markdown item, one or more source lines long.
+pub enum Block<'a> {
+    Heading(usize, String),
+    Paragraph(String),
+    // nesting depth and the marker to draw, `•` or the item's number
+    ListItem(usize, String, String),
+    Quote(String),
+    // fenced code is shown as written
+    Code(Vec<&'a str>),
+    Rule,
+    // whether the first row is a header, and the cells of every row
+    Table(bool, Vec<Vec<String>>),
+}
+
+// the cells of a `| a | b |` table row, if the line is one
+fn table_row(line: &str) -> Option<Vec<String>> {
+    let line = line.trim().strip_prefix('|')?;
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_owned()),
+            // escaped pipes stay escaped until the cell's inline text is read
+            '\\' => {
+                cell.push(c);
+                cell.extend(chars.next());
+            }
+            _ => cell.push(c),
+        }
+    }
+    if !cell.trim().is_empty() {
+        cells.push(cell.trim().to_owned());
+    }
+    Some(cells)
+}
+
+fn is_table_separator(cells: &[String]) -> bool {
+    cells
+        .iter()
+        .all(|cell| !cell.is_empty() && cell.chars().all(|c| c == '-' || c == ':'))
+}
+
+fn list_item(line: &str) -> Option<(usize, String, String)> {
+    let trimmed = line.trim_start();
+    let depth = (line.len() - trimmed.len()) / 2;
+    for bullet in &["- ", "* ", "+ "] {
+        if let Some(text) = trimmed.strip_prefix(bullet) {
+            return Some((depth, "•".to_string(), text.trim().to_owned()));
+        }
+    }
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        let rest = &trimmed[digits..];
+        if let Some(text) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            let number = format!("{}.", &trimmed[..digits]);
+            return Some((depth, number, text.trim().to_owned()));
+        }
+    }
+    None
+}
+
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    match &line[level..] {
+        "" => Some((level, String::new())),
+        rest if rest.starts_with(' ') => {
+            Some((level, rest.trim().trim_end_matches('#').trim().to_owned()))
+        }
+        _ => None,
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let line: String = line.split_whitespace().collect();
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|c| line.chars().all(|l| l == *c))
+}
+
+/// Splits markdown text into the blocks it's drawn as.
+///
+/// Lines of a paragraph, list item or quote are joined as markdown joins
+/// them, so they can be wrapped to whatever width the pane has.
+pub fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+    // whether a plain line continues the block above it
+    let mut open = false;
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            open = false;
+            continue;
+        }
+        if line.trim_start().starts_with("```") {
+            let code = lines
+                .by_ref()
+                .take_while(|line| !line.trim_start().starts_with("```"))
+                .collect();
+            blocks.push(Block::Code(code));
+            open = false;
+        } else if let Some((level, text)) = heading(line) {
+            blocks.push(Block::Heading(level, text));
+            open = false;
+        } else if is_rule(line) {
+            blocks.push(Block::Rule);
+            open = false;
+        } else if let Some(row) = table_row(line) {
+            match blocks.last_mut() {
+                Some(Block::Table(header, rows)) if open => {
+                    if rows.len() == 1 && !*header && is_table_separator(&row) {
+                        *header = true;
+                    } else {
+                        rows.push(row);
+                    }
+                }
+                _ => blocks.push(Block::Table(false, vec![row])),
+            }
+            open = true;
+        } else if let Some((depth, marker, text)) = list_item(line) {
+            blocks.push(Block::ListItem(depth, marker, text));
+            open = true;
+        } else if let Some(quote) = line.trim_start().strip_prefix('>') {
+            match blocks.last_mut() {
+                Some(Block::Quote(text)) if open => {
+                    text.push(' ');
+                    text.push_str(quote.trim());
+                }
+                _ => blocks.push(Block::Quote(quote.trim().to_owned())),
+            }
+            open = true;
+        } else {
+            match blocks.last_mut() {
+                Some(Block::Paragraph(text))
+                | Some(Block::ListItem(_, _, text))
+                | Some(Block::Quote(text))
+                    if open =>
+                {
+                    text.push(' ');
+                    text.push_str(line.trim());
+                }
+                _ => blocks.push(Block::Paragraph(line.trim().to_owned())),
+            }
+            open = true;
+        }
+    }
+    blocks
+}
+
+/// A piece of a block's text and how it should look.
+pub struct Run<'a> {
+    pub range: Range<usize>,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub link: Option<Link<'a>>,
+}
+
+/// Strips the inline markup from `text`, returning what's left to show
+/// along with runs covering all of it.
+///
+/// `[[Links]]` are kept as written, like paragraphs show them, while
+/// `[label](url)` and `[url label]` links are shortened to their label.
+pub fn inline(text: &str) -> (String, Vec<Run<'_>>) {
+    let mut out = String::new();
+    let mut runs: Vec<Run> = Vec::new();
+    let (mut bold, mut italic) = (false, false);
+    let mut i = 0;
+    // adds text to out, extending the last run when it looks the same
+    let push = |out: &mut String, runs: &mut Vec<Run<'_>>, text: &str, bold, italic, code| {
+        let start = out.len();
+        out.push_str(text);
+        match runs.last_mut() {
+            Some(run)
+                if run.link.is_none()
+                    && (run.bold, run.italic, run.code) == (bold, italic, code)
+                    && run.range.end == start =>
+            {
+                run.range.end = out.len()
+            }
+            _ => runs.push(Run {
+                range: start..out.len(),
+                bold,
+                italic,
+                code,
+                link: None,
+            }),
+        }
+    };
+    while i < text.len() {
+        let rest = &text[i..];
+        let before = text[..i].chars().last();
+        if let Some(escaped) = rest.strip_prefix('\\').and_then(|rest| rest.chars().next()) {
+            if escaped.is_ascii_punctuation() {
+                push(
+                    &mut out,
+                    &mut runs,
+                    &rest[1..1 + escaped.len_utf8()],
+                    bold,
+                    italic,
+                    false,
+                );
+                i += 1 + escaped.len_utf8();
+                continue;
+            }
+        }
+        if let Some(code) = rest.strip_prefix('`') {
+            if let Some(close) = code.find('`') {
+                push(&mut out, &mut runs, &code[..close], bold, italic, true);
+                i += close + 2;
+                continue;
+            }
+        }
+        if let Some(inner) = rest.strip_prefix("[[") {
+            if let Some(close) = inner.find("]]") {
+                let start = out.len();
+                out.push_str(&rest[..close + 4]);
+                runs.push(Run {
+                    range: start..out.len(),
+                    bold,
+                    italic,
+                    code: false,
+                    link: Some(Link::Internal(&inner[..close])),
+                });
+                i += close + 4;
+                continue;
+            }
+        }
+        if let Some(inner) = rest.strip_prefix('[') {
+            let link = match (inner.find("]("), inner.find(')')) {
+                (Some(middle), Some(close)) if middle < close && !inner[..middle].contains(']') => {
+                    let label = &inner[..middle];
+                    let url = &inner[middle + 2..close];
+                    Some((label, url, close + 2))
+                }
+                _ if inner.starts_with("http:") || inner.starts_with("https:") => {
+                    match (inner.find(' '), inner.find(']')) {
+                        (Some(space), Some(close)) if space < close => {
+                            Some((&inner[space + 1..close], &inner[..space], close + 2))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            if let Some((label, url, length)) = link {
+                let start = out.len();
+                out.push_str(label);
+                runs.push(Run {
+                    range: start..out.len(),
+                    bold,
+                    italic,
+                    code: false,
+                    link: Some(Link::External { url, label }),
+                });
+                i += length;
+                continue;
+            }
+        }
+        let marker = if rest.starts_with("**") || rest.starts_with("__") {
+            &rest[..2]
+        } else if rest.starts_with('*') || rest.starts_with('_') {
+            &rest[..1]
+        } else {
+            ""
+        };
+        if !marker.is_empty() {
+            let after = &rest[marker.len()..];
+            let open = if marker.len() == 2 { bold } else { italic };
+            // underscores inside words, as in snake_case, aren't emphasis
+            let in_word = marker.starts_with('_')
+                && before.is_some_and(char::is_alphanumeric)
+                && after.chars().next().is_some_and(char::is_alphanumeric);
+            let toggles = if open {
+                !before.is_none_or(char::is_whitespace)
+            } else {
+                !after.starts_with(char::is_whitespace) && after.contains(marker)
+            };
+            if toggles && !in_word {
+                if marker.len() == 2 {
+                    bold = !bold;
+                } else {
+                    italic = !italic;
+                }
+                i += marker.len();
+                continue;
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        push(
+            &mut out,
+            &mut runs,
+            &rest[..c.len_utf8()],
+            bold,
+            italic,
+            false,
+        );
+        i += c.len_utf8();
+    }
+    (out, runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // each block as a line of text, for comparing against
+    fn describe(text: &str) -> Vec<String> {
+        blocks(text)
+            .iter()
+            .map(|block| match block {
+                Block::Heading(level, text) => format!("h{} {}", level, text),
+                Block::Paragraph(text) => format!("p {}", text),
+                Block::ListItem(depth, marker, text) => format!("li{} {} {}", depth, marker, text),
+                Block::Quote(text) => format!("> {}", text),
+                Block::Code(lines) => format!("code {}", lines.join("|")),
+                Block::Rule => "rule".to_string(),
+                Block::Table(header, rows) => {
+                    let rows: Vec<String> = rows.iter().map(|row| row.join(",")).collect();
+                    format!("table {} {}", header, rows.join(";"))
+                }
+            })
+            .collect()
+    }
+
+    // the text of each run, marked with how it looks
+    fn runs(text: &str) -> (String, Vec<String>) {
+        let (out, runs) = inline(text);
+        let runs = runs
+            .iter()
+            .map(|run| {
+                let mut marks = String::new();
+                for (on, mark) in &[(run.bold, 'b'), (run.italic, 'i'), (run.code, 'c')] {
+                    if *on {
+                        marks.push(*mark);
+                    }
+                }
+                match &run.link {
+                    Some(Link::Internal(slug)) => marks.push_str(&format!("[{}]", slug)),
+                    Some(Link::External { url, .. }) => marks.push_str(&format!("({})", url)),
+                    None => {}
+                }
+                format!("{}:{}", marks, &out[run.range.clone()])
+            })
+            .collect();
+        (out, runs)
+    }
+
+    #[test]
+    fn paragraphs_join_their_lines() {
+        assert_eq!(describe("one\ntwo\n\nthree"), vec!["p one two", "p three"]);
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            describe("# Title #\n###### Six\n####### seven\n#nope\n---\n* * *"),
+            vec![
+                "h1 Title",
+                "h6 Six",
+                "p ####### seven #nope",
+                "rule",
+                "rule"
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_nest_and_continue() {
+        assert_eq!(
+            describe("- one\n  carried on\n  * two\n3. three\n4) four\n5.five"),
+            vec![
+                "li0 • one carried on",
+                "li1 • two",
+                "li0 3. three",
+                "li0 4. four 5.five",
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_join_their_lines() {
+        assert_eq!(
+            describe("> one\n>two\nthree\n\n> four"),
+            vec!["> one two three", "> four"]
+        );
+    }
+
+    #[test]
+    fn code_is_kept_as_written() {
+        assert_eq!(
+            describe("```rust\n  let *x* = 1;\n\n# not a heading\n```\nafter"),
+            vec!["code   let *x* = 1;||# not a heading", "p after"]
+        );
+        // an unclosed fence runs to the end
+        assert_eq!(describe("```\na\nb"), vec!["code a|b"]);
+    }
+
+    #[test]
+    fn tables_find_their_header() {
+        assert_eq!(
+            describe("| a | b |\n|---|:-:|\n| 1 | 2 \\| 3 |\n\n| x |\n| y |"),
+            vec!["table true a,b;1,2 \\| 3", "table false x;y"]
+        );
+    }
+
+    #[test]
+    fn emphasis_toggles() {
+        assert_eq!(
+            runs("a **bold *both*** _it_ __b__"),
+            (
+                "a bold both it b".to_string(),
+                vec![":a ", "b:bold ", "bi:both", ": ", "i:it", ": ", "b:b"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn unmatched_or_spaced_markers_are_text() {
+        assert_eq!(runs("2 * 3 * 4").1, vec![":2 * 3 * 4"]);
+        assert_eq!(runs("a *b").1, vec![":a *b"]);
+    }
+
+    #[test]
+    fn underscores_in_words_are_text() {
+        assert_eq!(runs("snake_case_name").1, vec![":snake_case_name"]);
+        assert_eq!(runs("_snake_case_").1, vec!["i:snake_case"]);
+    }
+
+    #[test]
+    fn escapes_drop_the_backslash() {
+        assert_eq!(runs(r"\*not\* a\b").1, vec![r":*not* a\b"]);
+    }
+
+    #[test]
+    fn code_spans_ignore_markup() {
+        assert_eq!(runs("**`a*b`**").1, vec!["bc:a*b"]);
+        assert_eq!(runs("`open").1, vec![":`open"]);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            runs("[[Hello World]] [label](http://x.org) [http://y.org why] [no link]"),
+            (
+                "[[Hello World]] label why [no link]".to_string(),
+                vec![
+                    "[Hello World]:[[Hello World]]",
+                    ": ",
+                    "(http://x.org):label",
+                    ": ",
+                    "(http://y.org):why",
+                    ": [no link]",
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect()
+            )
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+/// An image drawn over blank lines of a pane, on terminals that can.
+#[derive(Clone, Debug)]
+pub struct InlineImage {
+    // the first of the display lines it covers
+    pub line: usize,
+    pub rows: usize,
+    pub cols: usize,
+    pub png: Arc<Vec<u8>>,
+}
+
+pub fn is_media(r#type: &str) -> bool {
+    r#type == "image" || r#type == "video" || r#type == "audio"
+}
+
+/// The mime type and contents of a `data:` url.
+pub fn decode_data_url(url: &str) -> Option<(String, Vec<u8>)> {
+    let rest = url.strip_prefix("data:")?;
+    let comma = rest.find(',')?;
+    let (header, data) = (&rest[..comma], &rest[comma + 1..]);
+    let mime = header.split(';').next().unwrap_or("").to_owned();
+    let bytes = if header.ends_with(";base64") {
+        base64::decode(data.trim()).ok()?
+    } else {
+        data.as_bytes().to_vec()
+    };
+    Some((mime, bytes))
+}
+
+/// A file extension for a mime type, for saving media to disk.
+pub fn extension(mime: &str) -> &str {
+    match mime {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        "audio/mpeg" => "mp3",
+        _ => mime
+            .rsplit('/')
+            .next()
+            .filter(|ext| !ext.is_empty())
+            .unwrap_or("bin"),
+    }
+}
+
+/// The width and height of a png, gif or jpeg, read from its header.
+pub fn dimensions(image: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes([*image.get(at)?, *image.get(at + 1)?]) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(image.get(at..at + 4)?.try_into().ok()?));
+    if image.starts_with(b"\x89PNG") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if image.starts_with(b"GIF8") {
+        let le16 =
+            |at: usize| Some(u16::from_le_bytes([*image.get(at)?, *image.get(at + 1)?]) as u32);
+        return Some((le16(6)?, le16(8)?));
+    }
+    if image.starts_with(b"\xff\xd8") {
+        // walk the segments to the frame header, which holds the size
+        let mut at = 2;
+        while at + 9 < image.len() {
+            if image[at] != 0xff {
+                return None;
+            }
+            let marker = image[at + 1];
+            let is_frame = (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker);
+            if is_frame {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+/// Whether the terminal speaks the kitty graphics protocol.
+pub fn inline_images_supported() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    let program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    std::env::var("KITTY_WINDOW_ID").is_ok()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || program == "WezTerm"
+}
+
+/// The cells an image takes up when scaled to fit `max_cols` and a few rows.
+pub fn cells(width: u32, height: u32, max_cols: usize) -> (usize, usize) {
+    // cells are roughly twice as tall as they are wide
+    let (cell_width, cell_height) = (8.0, 16.0);
+    let mut cols = (width as f64 / cell_width).ceil();
+    let mut rows = (height as f64 / cell_height).ceil();
+    let scale = (max_cols as f64 / cols).min(20.0 / rows).min(1.0);
+    cols = (cols * scale).max(1.0);
+    rows = (rows * scale).max(1.0);
+    (cols as usize, rows as usize)
+}
+
+/// The escape sequence that draws a png at the cursor, filling the given cells.
+///
+/// Images are drawn on a `layer` so each pane can clear its own.
+pub fn kitty_image(png: &[u8], cols: usize, rows: usize, layer: usize) -> String {
+    let data = base64::encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = std::str::from_utf8(chunk).unwrap_or("");
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,q=2,c={},r={},z={},m={};{}\x1b\\",
+                cols, rows, layer, more, chunk
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    out
+}
+
+/// The escape sequence that clears every image drawn with `kitty_image`.
+pub fn clear_kitty_images() -> &'static str {
+    "\x1b_Ga=d,q=2\x1b\\"
+}
+
+/// The escape sequence that clears the images `kitty_image` drew on a layer.
+pub fn clear_kitty_layer(layer: usize) -> String {
+    format!("\x1b_Ga=d,d=z,z={},q=2\x1b\\", layer)
+}
+
+/// Where a video item's media lives, from the `YOUTUBE id` style line it starts with.
+pub fn video_url(text: &str) -> Option<String> {
+    let mut words = text.lines().next()?.split_whitespace();
+    let (kind, id) = (words.next()?, words.next()?);
+    match kind {
+        "YOUTUBE" => Some(format!("https://www.youtube.com/watch?v={}", id)),
+        "VIMEO" => Some(format!("https://vimeo.com/{}", id)),
+        "ARCHIVE" => Some(format!("https://archive.org/details/{}", id)),
+        _ if id.starts_with("http") => Some(id.to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a png's signature and header chunk, enough to read its size from
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(&width.to_be_bytes());
+        png.extend(&height.to_be_bytes());
+        png.extend(b"\x08\x06\0\0\0");
+        png
+    }
+
+    #[test]
+    fn data_urls() {
+        assert_eq!(
+            decode_data_url("data:image/png;base64,aGk="),
+            Some(("image/png".to_string(), b"hi".to_vec()))
+        );
+        assert_eq!(
+            decode_data_url("data:text/plain,hi there"),
+            Some(("text/plain".to_string(), b"hi there".to_vec()))
+        );
+        assert_eq!(
+            decode_data_url("data:,x"),
+            Some((String::new(), b"x".to_vec()))
+        );
+        assert_eq!(decode_data_url("data:image/png;base64,!!"), None);
+        assert_eq!(decode_data_url("data:image/png"), None);
+        assert_eq!(decode_data_url("http://example.com/a.png"), None);
+    }
+
+    #[test]
+    fn png_dimensions() {
+        assert_eq!(dimensions(&png(640, 480)), Some((640, 480)));
+        assert_eq!(dimensions(&png(640, 480)[..22]), None);
+    }
+
+    #[test]
+    fn gif_dimensions() {
+        let gif = b"GIF89a\x80\x02\xe0\x01\xf7\0\0";
+        assert_eq!(dimensions(gif), Some((640, 480)));
+        assert_eq!(dimensions(&gif[..8]), None);
+    }
+
+    #[test]
+    fn jpeg_dimensions() {
+        let mut jpeg = b"\xff\xd8".to_vec();
+        // an app0 segment and a huffman table come before the frame header
+        jpeg.extend(b"\xff\xe0\0\x10JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        jpeg.extend(b"\xff\xc4\0\x04\0\0");
+        jpeg.extend(b"\xff\xc2\0\x11\x08\x01\xe0\x02\x80\x03\x01\x22\0\x02\x11\x01\x03\x11\x01");
+        assert_eq!(dimensions(&jpeg), Some((640, 480)));
+        // cut off before the frame header is reached
+        assert_eq!(dimensions(&jpeg[..24]), None);
+        assert_eq!(dimensions(&jpeg[..30]), None);
+        // segments that don't start with a marker
+        assert_eq!(dimensions(b"\xff\xd8\0\xe0\0\x10JFIF\0\x01\x01\0"), None);
+    }
+
+    #[test]
+    fn other_files_have_no_dimensions() {
+        assert_eq!(dimensions(b""), None);
+        assert_eq!(dimensions(b"<svg width=\"10\"/>"), None);
+    }
+}
@@ -0,0 +1,361 @@
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A page as listed in a site's `system/sitemap.json`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SitemapEntry {
+    pub slug: String,
+    #[serde(default)]
+    pub title: String,
+    pub date: Option<u64>,
+    pub synopsis: Option<String>,
+}
+
+pub async fn fetch_sitemap(url: &str) -> Result<Vec<SitemapEntry>, Error> {
+    let sitemap_url = Url::parse(url)?.join("system/sitemap.json")?;
+    let response = reqwest::get(sitemap_url).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Unable to get sitemap: {}",
+            response.status().as_str()
+        ));
+    }
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+// the words a site's pages contain, mapped to the slugs of those pages
+type SiteIndex = HashMap<String, HashSet<String>>;
+
+// collects terms from a minisearch radix tree, where each key continues the prefix
+fn index_tree(prefix: &str, tree: &Map<String, Value>, terms: &mut Vec<(String, Value)>) {
+    for (key, value) in tree {
+        if key.is_empty() {
+            terms.push((prefix.to_owned(), value.clone()));
+        } else if let Value::Object(subtree) = value {
+            index_tree(&format!("{}{}", prefix, key), subtree, terms);
+        }
+    }
+}
+
+// reads the minisearch index the wiki server writes to `system/site-index.json`
+fn parse_site_index(index: &Value) -> Option<SiteIndex> {
+    let ids = index.get("documentIds")?.as_object()?;
+    let terms = match index.get("index")? {
+        // serialization version 2 lists [term, fields] pairs
+        Value::Array(terms) => terms
+            .iter()
+            .filter_map(|term| Some((term.get(0)?.as_str()?.to_owned(), term.get(1)?.clone())))
+            .collect(),
+        // older versions keep the radix tree itself
+        Value::Object(tree) => {
+            let mut terms = Vec::new();
+            index_tree("", tree.get("_tree")?.as_object()?, &mut terms);
+            terms
+        }
+        _ => return None,
+    };
+    let mut site_index = SiteIndex::new();
+    for (term, fields) in terms {
+        let slugs = site_index.entry(term).or_default();
+        for field in fields.as_object()?.values() {
+            // older versions wrap the documents in `ds`
+            let documents = field.get("ds").unwrap_or(field);
+            for id in documents.as_object()?.keys() {
+                if let Some(slug) = ids.get(id).and_then(Value::as_str) {
+                    slugs.insert(slug.to_owned());
+                }
+            }
+        }
+    }
+    Some(site_index)
+}
+
+async fn fetch_site_index(url: &str) -> Result<SiteIndex, Error> {
+    let index_url = Url::parse(url)?.join("system/site-index.json")?;
+    let response = reqwest::get(index_url).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Unable to get site index: {}",
+            response.status().as_str()
+        ));
+    }
+    let index: Value = serde_json::from_str(&response.text().await?)?;
+    parse_site_index(&index).ok_or_else(|| anyhow!("Unable to read site index!"))
+}
+
+/// A site in the neighborhood, and where fetching its sitemap is at.
+#[derive(Debug, Clone)]
+pub struct Neighbor {
+    pub site: String,
+    pub status: String,
+}
+
+pub enum Sitemap {
+    Fetching,
+    Fetched(Vec<SitemapEntry>),
+    Failed(String),
+}
+
+/// The sites mentioned by pages we've visited, with their sitemaps.
+///
+/// Sitemaps are fetched in the background as sites are discovered, so
+/// lookups only see the ones that have arrived so far.
+pub struct Neighborhood {
+    // in the order they were discovered
+    sites: Vec<String>,
+    sitemaps: Arc<Mutex<HashMap<String, Sitemap>>>,
+    // only for the sites that publish one
+    indexes: Arc<Mutex<HashMap<String, SiteIndex>>>,
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Neighborhood {
+    pub fn new() -> Neighborhood {
+        Neighborhood {
+            sites: Vec::new(),
+            sitemaps: Arc::new(Mutex::new(HashMap::new())),
+            indexes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn sites(&self) -> &[String] {
+        &self.sites
+    }
+
+    /// Adds a site served from `url`, fetching its sitemap the first time it's seen.
+    pub fn add(&mut self, site: &str, url: &str) {
+        if self.sites.iter().any(|known| known == site) {
+            return;
+        }
+        self.sites.push(site.to_owned());
+        self.sitemaps
+            .lock()
+            .unwrap()
+            .insert(site.to_owned(), Sitemap::Fetching);
+        let sitemaps = self.sitemaps.clone();
+        let indexes = self.indexes.clone();
+        let site = site.to_owned();
+        let url = url.to_owned();
+        tokio::spawn(async move {
+            let sitemap = match fetch_sitemap(&url).await {
+                Ok(entries) => Sitemap::Fetched(entries),
+                Err(err) => Sitemap::Failed(err.to_string()),
+            };
+            sitemaps.lock().unwrap().insert(site.clone(), sitemap);
+            if let Ok(index) = fetch_site_index(&url).await {
+                indexes.lock().unwrap().insert(site, index);
+            }
+        });
+    }
+
+    /// Adds a site whose pages are already known, such as a local wiki, or updates them.
+    pub fn add_sitemap(&mut self, site: &str, entries: Vec<SitemapEntry>) {
+        if !self.sites.iter().any(|known| known == site) {
+            self.sites.push(site.to_owned());
+        }
+        self.sitemaps
+            .lock()
+            .unwrap()
+            .insert(site.to_owned(), Sitemap::Fetched(entries));
+    }
+
+    /// Every site found so far, in the order they were discovered.
+    pub fn neighbors(&self) -> Vec<Neighbor> {
+        self.sites
+            .iter()
+            .map(|site| Neighbor {
+                site: site.clone(),
+                status: self.status(site),
+            })
+            .collect()
+    }
+
+    /// The pages a site lists, if its sitemap has arrived.
+    pub fn sitemap(&self, site: &str) -> Option<Vec<SitemapEntry>> {
+        match self.sitemaps.lock().unwrap().get(site) {
+            Some(Sitemap::Fetched(entries)) => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    /// A one line description of where a site's sitemap is at.
+    pub fn status(&self, site: &str) -> String {
+        match self.sitemaps.lock().unwrap().get(site) {
+            Some(Sitemap::Fetched(entries)) => format!("{} pages", entries.len()),
+            Some(Sitemap::Failed(err)) => format!("failed: {}", err),
+            Some(Sitemap::Fetching) | None => "fetching...".to_string(),
+        }
+    }
+
+    /// The sites whose sitemaps list `slug`, in the order they were discovered.
+    pub fn find(&self, slug: &str) -> Vec<String> {
+        let sitemaps = self.sitemaps.lock().unwrap();
+        self.sites
+            .iter()
+            .filter(|site| match sitemaps.get(*site) {
+                Some(Sitemap::Fetched(entries)) => entries.iter().any(|entry| entry.slug == slug),
+                _ => false,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The pages whose title or synopsis (or site index entry) has every term.
+    pub fn search(&self, query: &str) -> Vec<(String, SitemapEntry)> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let sitemaps = self.sitemaps.lock().unwrap();
+        let indexes = self.indexes.lock().unwrap();
+        let mut hits = Vec::new();
+        for site in &self.sites {
+            let entries = match sitemaps.get(site) {
+                Some(Sitemap::Fetched(entries)) => entries,
+                _ => continue,
+            };
+            // the slugs the site index has for each term, matching words by prefix
+            let indexed: Vec<HashSet<&String>> = terms
+                .iter()
+                .map(|term| match indexes.get(site) {
+                    Some(index) => index
+                        .iter()
+                        .filter(|(word, _)| word.starts_with(term.as_str()))
+                        .flat_map(|(_, slugs)| slugs)
+                        .collect(),
+                    None => HashSet::new(),
+                })
+                .collect();
+            for entry in entries {
+                let text = format!(
+                    "{} {}",
+                    entry.title,
+                    entry.synopsis.as_deref().unwrap_or_default()
+                )
+                .to_lowercase();
+                let matches = terms.iter().zip(&indexed).all(|(term, slugs)| {
+                    text.contains(term.as_str()) || slugs.contains(&entry.slug)
+                });
+                if matches {
+                    hits.push((site.clone(), entry.clone()));
+                }
+            }
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn slugs(index: &SiteIndex, term: &str) -> Vec<String> {
+        let mut slugs: Vec<String> = index.get(term).into_iter().flatten().cloned().collect();
+        slugs.sort();
+        slugs
+    }
+
+    fn entry(slug: &str, title: &str, synopsis: &str) -> SitemapEntry {
+        SitemapEntry {
+            slug: slug.to_string(),
+            title: title.to_string(),
+            date: None,
+            synopsis: Some(synopsis.to_string()),
+        }
+    }
+
+    #[test]
+    fn site_index_pairs() {
+        let index = parse_site_index(&json!({
+            "documentIds": {"1": "welcome-visitors", "2": "hello-world"},
+            "index": [
+                ["hello", {"0": {"1": 1, "2": 2}}],
+                ["world", {"0": {"2": 1}, "1": {"3": 1}}]
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            slugs(&index, "hello"),
+            vec!["hello-world", "welcome-visitors"]
+        );
+        // documents the ids don't list are left out
+        assert_eq!(slugs(&index, "world"), vec!["hello-world"]);
+    }
+
+    #[test]
+    fn site_index_tree() {
+        let index = parse_site_index(&json!({
+            "documentIds": {"1": "welcome-visitors", "2": "hello-world"},
+            "index": {"_tree": {
+                "hel": {
+                    "lo": {"": {"0": {"ds": {"1": 1, "2": 1}}}},
+                    "p": {"": {"1": {"ds": {"2": 3}}}}
+                },
+                "world": {"": {"0": {"ds": {"2": 1}}}}
+            }}
+        }))
+        .unwrap();
+        assert_eq!(
+            slugs(&index, "hello"),
+            vec!["hello-world", "welcome-visitors"]
+        );
+        assert_eq!(slugs(&index, "help"), vec!["hello-world"]);
+        assert_eq!(slugs(&index, "world"), vec!["hello-world"]);
+        assert!(!index.contains_key("hel"));
+    }
+
+    #[test]
+    fn site_index_of_something_else() {
+        assert!(parse_site_index(&json!({"index": []})).is_none());
+        assert!(parse_site_index(&json!({"documentIds": {}, "index": 7})).is_none());
+    }
+
+    #[test]
+    fn search_needs_every_term() {
+        let mut neighborhood = Neighborhood::new();
+        neighborhood.add_sitemap(
+            "one.example",
+            vec![
+                entry("apples", "Apples", "Red fruit."),
+                entry("pears", "Pears", "Green fruit."),
+            ],
+        );
+        neighborhood.add_sitemap("two.example", vec![entry("plums", "Plums", "Purple.")]);
+        let mut index = SiteIndex::new();
+        index.insert(
+            "orchard".to_string(),
+            vec!["plums".to_string()].into_iter().collect(),
+        );
+        neighborhood
+            .indexes
+            .lock()
+            .unwrap()
+            .insert("two.example".to_string(), index);
+        let search = |query| {
+            neighborhood
+                .search(query)
+                .into_iter()
+                .map(|(site, entry)| format!("{} {}", site, entry.slug))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            search("FRUIT"),
+            vec!["one.example apples", "one.example pears"]
+        );
+        assert_eq!(search("red fruit"), vec!["one.example apples"]);
+        // site index words match by prefix
+        assert_eq!(search("orch purple"), vec!["two.example plums"]);
+        assert!(search("red purple").is_empty());
+        assert!(search("  ").is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::DisplayLine;
+use crate::{media, DisplayLine, InlineImage, LinkTarget, Rendering};
 use anyhow::{Error, Result};
 use crossterm::{
     cursor,
@@ -6,6 +6,7 @@ use crossterm::{
     terminal::{Clear, ClearType, ScrollDown, ScrollUp},
     QueueableCommand,
 };
+use std::cmp::min;
 use std::io::{stdout, Stdout, Write};
 
 struct Search {
@@ -19,23 +20,200 @@ pub struct Pane {
     lines: Vec<DisplayLine>,
     display_lines: Vec<DisplayLine>,
     current_search: Option<Search>,
+    // the line and span where the focused link starts
+    focused: Option<(usize, usize)>,
     pub scroll_index: usize,
     pub highlight_index: Option<usize>,
     size: (usize, usize),
+    // the screen column the pane starts at
+    column: usize,
+    // whether other panes are shown beside this one
+    shared: bool,
+    pub active: bool,
+    // what the lines show, as rendered along with them
+    line_items: Vec<Option<usize>>,
+    links: Vec<(String, LinkTarget)>,
+    images: Vec<InlineImage>,
+}
+
+// cuts styled text to `width` columns, keeping its escape sequences,
+// and returns it with the columns it takes up
+fn clip(text: &str, width: usize) -> (String, usize) {
+    let mut clipped = String::new();
+    let mut cols = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            clipped.push(c);
+            // control sequences end with a letter
+            for c in chars.by_ref() {
+                clipped.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        if cols == width {
+            // so a style cut short doesn't carry on into the next pane
+            clipped.push_str("\x1b[0m");
+            break;
+        }
+        clipped.push(c);
+        cols += 1;
+    }
+    (clipped, cols)
 }
 
 impl Pane {
     // TODO: Remove dependency on wiki::DisplayLine
-    pub fn new(lines: Vec<DisplayLine>, size: (usize, usize)) -> Pane {
+    pub fn new(rendering: Rendering, size: (usize, usize)) -> Pane {
+        let lines = rendering.lines;
         Pane {
             header: "".to_string(),
-            lines: lines.clone(),
-            display_lines: lines,
+            display_lines: lines.iter().map(DisplayLine::styled).collect(),
+            lines,
             current_search: None,
+            focused: None,
             scroll_index: 0,
             highlight_index: None,
             size,
+            column: 0,
+            shared: false,
+            active: true,
+            line_items: rendering.line_items,
+            links: rendering.links,
+            images: rendering.images,
+        }
+    }
+
+    /// Places the pane on screen, starting at `column` and `size` cells large.
+    ///
+    /// `shared` is set when other panes are shown beside it.
+    pub fn place(&mut self, column: usize, size: (usize, usize), shared: bool) {
+        self.column = column;
+        self.size = size;
+        self.shared = shared;
+    }
+
+    pub fn width(&self) -> usize {
+        self.size.0
+    }
+
+    /// Whether the given screen column falls within the pane.
+    pub fn contains(&self, x: u16) -> bool {
+        let x = x as usize;
+        x >= self.column && x < self.column + self.size.0
+    }
+
+    /// Swaps in freshly rendered lines, keeping the scroll position and highlight.
+    pub fn set_lines(&mut self, rendering: Rendering) -> Result<(), Error> {
+        self.display_lines = rendering.lines.iter().map(DisplayLine::styled).collect();
+        self.lines = rendering.lines;
+        self.line_items = rendering.line_items;
+        self.links = rendering.links;
+        self.images = rendering.images;
+        self.current_search = None;
+        self.focused = None;
+        self.scroll_index = min(self.scroll_index, self.lines.len().saturating_sub(1));
+        if let Some(highlight_index) = self.highlight_index {
+            // the highlighted item may have been removed from the end
+            if self.line_to_display(highlight_index).is_none() {
+                self.highlight_index = self.lines.iter().rev().find_map(|line| line.line_index);
+            }
+            // and there's nothing left to highlight once the last item goes
+            if self.highlight_index.is_some() {
+                self.highlight_line()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Swaps in lines wrapped to a new width, keeping the top item in view.
+    pub fn rewrap(&mut self, rendering: Rendering) -> Result<(), Error> {
+        let top = self.lines[..min(self.scroll_index + 1, self.lines.len())]
+            .iter()
+            .rev()
+            .find_map(|line| line.line_index);
+        self.set_lines(rendering)?;
+        if let Some(line) = top.and_then(|top| self.line_to_display(top)) {
+            self.scroll_index = line;
         }
+        Ok(())
+    }
+
+    /// Scrolls to and highlights what was saved for the pane, if it's still there.
+    pub fn restore(
+        &mut self,
+        scroll_index: usize,
+        highlight_index: Option<usize>,
+    ) -> Result<(), Error> {
+        self.scroll_index = min(scroll_index, self.lines.len().saturating_sub(1));
+        self.highlight_index =
+            highlight_index.filter(|index| self.line_to_display(*index).is_some());
+        if self.highlight_index.is_some() {
+            self.highlight_line()?;
+        }
+        Ok(())
+    }
+
+    /// The story item shown on the given line, if any.
+    pub fn item_at(&self, line: usize) -> Option<usize> {
+        self.line_items.get(line).cloned().flatten()
+    }
+
+    /// The first line of the given story item.
+    pub fn item_line(&self, index: usize) -> Option<usize> {
+        self.line_items.iter().position(|item| *item == Some(index))
+    }
+
+    /// The text and target of a link, as numbered by the spans that follow it.
+    pub fn link(&self, index: usize) -> Option<&(String, LinkTarget)> {
+        self.links.get(index)
+    }
+
+    // draws the images that fit on screen entirely, clearing any others
+    fn queue_images(&self, stdout: &mut Stdout) -> Result<(), Error> {
+        if !media::inline_images_supported() {
+            return Ok(());
+        }
+        write!(stdout, "{}", media::clear_kitty_layer(self.column))?;
+        let end = self.scroll_index + self.size.1 - 2;
+        for image in &self.images {
+            if image.line >= self.scroll_index && image.line + image.rows <= end {
+                stdout.queue(cursor::MoveTo(
+                    self.column as u16,
+                    (1 + image.line - self.scroll_index) as u16,
+                ))?;
+                write!(
+                    stdout,
+                    "{}",
+                    media::kitty_image(&image.png, image.cols, image.rows, self.column)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The display line at the start of the highlighted item.
+    /// Whether there's nothing on the page to show, as on a freshly created one.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn highlighted_line(&self) -> Option<usize> {
+        self.highlight_index
+            .and_then(|highlight_index| self.line_to_display(highlight_index))
+    }
+
+    /// Moves the highlight to the item rendered on the given display line.
+    pub fn highlight_display_line(&mut self, line: usize) -> Result<(), Error> {
+        if let Some(line_index) = self.lines.get(line).and_then(|line| line.line_index) {
+            self.reset_line(self.highlight_index);
+            self.highlight_index = Some(line_index);
+            self.highlight_line()?;
+        }
+        Ok(())
     }
 
     fn single_line(
@@ -51,14 +229,25 @@ impl Pane {
         Ok(())
     }
 
+    // draws text on a row of the pane, cut to fit and blanking the rest of the row
+    fn queue_row(&self, stdout: &mut Stdout, row: usize, text: &str) -> Result<(), Error> {
+        stdout.queue(cursor::MoveTo(self.column as u16, row as u16))?;
+        let (text, cols) = clip(text, self.size.0);
+        let blank = " ".repeat(self.size.0 - cols);
+        write!(stdout, "{}{}", text, blank)?;
+        Ok(())
+    }
+
     fn queue_header(&self, stdout: &mut Stdout) -> Result<(), Error> {
-        self.single_line(
-            stdout,
-            (0, 0),
-            &style(format!("{: ^1$}", self.header, self.size.0 as usize))
-                .attribute(Attribute::Reverse)
-                .to_string(),
-        )
+        let header: String = format!("{: ^1$}", self.header, self.size.0)
+            .chars()
+            .take(self.size.0)
+            .collect();
+        let mut header = style(header).attribute(Attribute::Reverse);
+        if !self.active {
+            header = header.attribute(Attribute::Dim);
+        }
+        self.queue_row(stdout, 0, &header.to_string())
     }
 
     pub fn header(&self) -> Result<(), Error> {
@@ -68,18 +257,18 @@ impl Pane {
         Ok(())
     }
 
+    // the bottom line, shared with the ex line
     pub fn status(&self, status: &str) -> Result<(), Error> {
         let mut stdout = stdout();
-        self.single_line(&mut stdout, (0, self.size.1 as u16), status)?;
+        self.single_line(&mut stdout, (0, self.size.1 as u16 - 1), status)?;
         stdout.flush()?;
         Ok(())
     }
 
     pub fn display(&mut self) -> Result<(), Error> {
         self.header()?;
-        let lines = &mut self.display_lines;
+        let lines = &self.display_lines;
         let mut stdout = stdout();
-        stdout.queue(cursor::MoveTo(0, 1))?;
         // Reuse for scroll to bottom?
         // let offset = if lines.len() >= self.size.1 as usize {
         //     lines.len() - self.size.1 as usize + 1
@@ -88,10 +277,11 @@ impl Pane {
         // };
         let mut count = 0;
         for (i, line) in lines.iter().enumerate().skip(self.scroll_index) {
-            stdout.queue(Clear(ClearType::CurrentLine))?;
             let mut line = line.clone();
             if let Some(search) = &self.current_search {
                 if search.line == i {
+                    // search offsets are into the unstyled text
+                    line = self.lines[i].clone();
                     line.text.replace_range(
                         search.index..search.index + search.pattern.len(),
                         &style(&search.pattern)
@@ -100,41 +290,146 @@ impl Pane {
                     );
                 }
             }
-            write!(stdout, "{}", line.text)?;
+            self.queue_row(&mut stdout, 1 + count, &line.text)?;
             count += 1;
-            stdout.queue(cursor::MoveToNextLine(1))?;
             // target is size minus header and status lines.
             if count >= self.size.1 - 2 {
                 break;
             }
         }
-        for _ in count..self.size.1 - 2 {
-            stdout.queue(Clear(ClearType::CurrentLine))?;
-            stdout.queue(cursor::MoveToNextLine(1))?;
+        for row in count..self.size.1 - 2 {
+            self.queue_row(&mut stdout, 1 + row, "")?;
         }
+        self.queue_images(&mut stdout)?;
         stdout.flush()?;
         Ok(())
     }
 
-    pub fn find_link(&self, x: u16, y: u16) -> Option<String> {
-        if y as usize >= self.lines.len() - self.scroll_index {
-            return None;
+    /// The link under the given screen position, as an index for `link`.
+    pub fn find_link(&self, x: u16, y: u16) -> Option<usize> {
+        let x = x.checked_sub(self.column as u16)?;
+        let line = self.lines.get(self.scroll_index + y as usize)?;
+        let (offset, _) = line.text.char_indices().nth(x as usize)?;
+        line.spans
+            .iter()
+            .find(|span| span.start <= offset && offset < span.end)
+            .and_then(|span| span.link)
+    }
+
+    // whether a span carries on a link wrapped from the end of the line before
+    fn continues_link(&self, line: usize, span: usize) -> bool {
+        let span = &self.lines[line].spans[span];
+        if line == 0 || span.start != 0 {
+            return false;
+        }
+        let previous = &self.lines[line - 1];
+        match previous.spans.last() {
+            Some(last) => last.link == span.link && last.end == previous.text.len(),
+            None => false,
+        }
+    }
+
+    // the line and span of every piece of the link starting at `start`
+    fn link_pieces(&self, start: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut pieces = vec![start];
+        let mut line = start.0 + 1;
+        while line < self.lines.len()
+            && !self.lines[line].spans.is_empty()
+            && self.continues_link(line, 0)
+        {
+            pieces.push((line, 0));
+            line += 1;
         }
-        let line = &self.lines[self.scroll_index + y as usize];
-        if x as usize >= line.text.len() {
-            return None;
+        pieces
+    }
+
+    // where each link on screen starts
+    fn visible_links(&self) -> Vec<(usize, usize)> {
+        let end = min(self.scroll_index + self.size.1 - 2, self.lines.len());
+        let mut starts = Vec::new();
+        for line in self.scroll_index..end {
+            for (span, current) in self.lines[line].spans.iter().enumerate() {
+                if current.link.is_some() && !self.continues_link(line, span) {
+                    starts.push((line, span));
+                }
+            }
         }
-        let start = line.text[0..x as usize].rfind("[[");
-        let end = start.and_then(|start| line.text[start..line.text.len()].find("]]"));
-        match (start, end) {
-            (Some(start), Some(end)) => Some(line.text[start + 2..end].to_string()),
-            _ => None,
+        starts
+    }
+
+    fn set_focus(&mut self, focus: Option<(usize, usize)>) {
+        if let Some(old) = self.focused.take() {
+            for (line, _) in self.link_pieces(old) {
+                self.display_lines[line] = self.lines[line].styled();
+            }
         }
+        if let Some(new) = focus {
+            for (line, span) in self.link_pieces(new) {
+                let mut focused = self.lines[line].clone();
+                let style = &mut focused.spans[span].style;
+                *style = style.clone().attribute(Attribute::Reverse);
+                self.display_lines[line] = focused.styled();
+            }
+        }
+        self.focused = focus;
+    }
+
+    /// Moves the focus to the next (or previous) link on screen, wrapping around.
+    pub fn focus_link(&mut self, forward: bool) -> Result<(), Error> {
+        let starts = self.visible_links();
+        if starts.is_empty() {
+            self.set_focus(None);
+            return self.display();
+        }
+        let current = self
+            .focused
+            .and_then(|focused| starts.iter().position(|start| *start == focused));
+        let next = match current {
+            Some(i) if forward => (i + 1) % starts.len(),
+            Some(i) => (i + starts.len() - 1) % starts.len(),
+            None if forward => 0,
+            None => starts.len() - 1,
+        };
+        self.set_focus(Some(starts[next]));
+        self.display()
+    }
+
+    /// The focused link, as an index for `link`.
+    pub fn focused_link(&self) -> Option<usize> {
+        self.focused
+            .and_then(|(line, span)| self.lines[line].spans[span].link)
+    }
+
+    /// Marks the next link after the current one, much like `search_next`.
+    pub fn next_link(&mut self) -> Result<(), Error> {
+        let (line, offset) = match self.current_search.take() {
+            Some(search) => {
+                self.display_lines[search.line] = self.lines[search.line].styled();
+                (search.line, search.index + 1)
+            }
+            None => (self.scroll_index, 0),
+        };
+        for (i, current) in self.lines.iter().enumerate().skip(line) {
+            let from = if i == line { offset } else { 0 };
+            let next = current
+                .spans
+                .iter()
+                .find(|span| span.link.is_some() && span.start >= from);
+            if let Some(span) = next {
+                self.current_search = Some(Search {
+                    line: i,
+                    index: span.start,
+                    pattern: current.text[span.start..span.end].to_string(),
+                });
+                break;
+            }
+        }
+        Ok(())
     }
 
     fn find_search(&self, pattern: &str, offset: usize) -> Option<Search> {
         for (i, line) in self.lines.iter().enumerate().skip(offset) {
-            let index = line.text.find(&pattern);
+            let index = line.text.find(pattern);
             if let Some(index) = index {
                 return Some(Search {
                     line: i,
@@ -143,10 +438,12 @@ impl Pane {
                 });
             }
         }
-        return None;
+        None
     }
 
     pub fn reset_line(&mut self, highlight_index: Option<usize>) {
+        // repainting the line would leave a focused link on it looking unfocused
+        self.set_focus(None);
         if let Some(highlight_index) = highlight_index {
             let line = self.line_to_display(highlight_index);
             if let Some(line) = line {
@@ -155,7 +452,7 @@ impl Pane {
                     let current_line = &self.lines[i];
                     if let Some(index) = current_line.line_index {
                         if index == target_index {
-                            self.display_lines[i] = self.lines[i].clone();
+                            self.display_lines[i] = self.lines[i].styled();
                             continue;
                         }
                     }
@@ -166,6 +463,10 @@ impl Pane {
     }
 
     pub fn highlight_line(&mut self) -> Result<(), Error> {
+        self.set_focus(None);
+        if self.lines.is_empty() {
+            return Ok(());
+        }
         let line = match self.highlight_index {
             None => self.scroll_index,
             Some(line) => line,
@@ -241,7 +542,7 @@ impl Pane {
     }
 
     pub fn highlight_prev(&mut self) -> Result<Option<usize>, Error> {
-        if let Some(highlight_index) = self.highlight_index {
+        if let Some(highlight_index) = self.highlight_index.filter(|index| *index > 0) {
             if let Some(display_line) = self.line_to_display(highlight_index - 1) {
                 self.reset_line(self.highlight_index);
                 self.highlight_index = Some(highlight_index - 1);
@@ -274,8 +575,8 @@ impl Pane {
         // reset if new pattern isn't the same as the old one
         match &self.current_search {
             Some(search) if search.pattern != pattern => {
-                self.display_lines[search.line] = self.lines[search.line].clone();
-                std::mem::replace(&mut self.current_search, None);
+                self.display_lines[search.line] = self.lines[search.line].styled();
+                self.current_search.take();
                 self.status("changed")?;
                 return Ok(());
             }
@@ -310,16 +611,14 @@ impl Pane {
     }
 
     fn line_to_display(&self, target_index: usize) -> Option<usize> {
-        let mut display_index = 0;
-        for line in &self.display_lines {
+        for (display_index, line) in self.display_lines.iter().enumerate() {
             if let Some(line_index) = line.line_index {
                 if line_index == target_index {
                     return Some(display_index);
                 }
             }
-            display_index += 1;
         }
-        return None;
+        None
     }
 
     pub fn line_span(&self, target_index: usize) -> Option<usize> {
@@ -342,6 +641,12 @@ impl Pane {
     }
 
     pub fn scroll_down(&mut self, scroll_by: usize) -> Result<(), Error> {
+        // images and panes alongside don't move with the text, so redraw everything instead
+        if !self.images.is_empty() || self.shared {
+            let last = self.lines.len().saturating_sub(self.size.1 - 2);
+            self.scroll_index = min(self.scroll_index + scroll_by, last.max(self.scroll_index));
+            return self.display();
+        }
         if self.scroll_index + self.size.1 - 2 < self.lines.len() {
             let mut stdout = stdout();
             stdout.queue(ScrollUp(scroll_by as u16))?;
@@ -365,6 +670,10 @@ impl Pane {
     }
 
     pub fn scroll_up(&mut self, scroll_by: usize) -> Result<(), Error> {
+        if !self.images.is_empty() || self.shared {
+            self.scroll_index -= min(scroll_by, self.scroll_index);
+            return self.display();
+        }
         if self.scroll_index > 0 {
             let mut stdout = stdout();
             stdout.queue(ScrollDown(scroll_by as u16))?;
@@ -387,3 +696,27 @@ impl Pane {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_leaves_short_text() {
+        assert_eq!(clip("café", 10), ("café".to_string(), 4));
+        assert_eq!(clip("", 10), (String::new(), 0));
+    }
+
+    #[test]
+    fn clip_cuts_long_text() {
+        assert_eq!(clip("café crème", 4), ("café\x1b[0m".to_string(), 4));
+        assert_eq!(clip("abc", 0), ("\x1b[0m".to_string(), 0));
+    }
+
+    #[test]
+    fn clip_skips_escape_sequences() {
+        let styled = "\x1b[1mbold\x1b[0m text";
+        assert_eq!(clip(styled, 20), (styled.to_string(), 9));
+        assert_eq!(clip(styled, 2), ("\x1b[1mbo\x1b[0m".to_string(), 2));
+    }
+}
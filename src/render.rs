@@ -0,0 +1,184 @@
+use std::ops::Range;
+
+/// Byte ranges of `text` broken into lines of at most `width` columns.
+///
+/// Unlike `textwrap`, the ranges point back into `text` so that links and
+/// styles found in the unwrapped text can be carried onto each line.
+pub fn wrap(text: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    // the current line runs from start to end and is cols wide
+    let (mut start, mut end, mut cols) = (0, 0, 0);
+    let mut offset = 0;
+    for word in text.split(' ') {
+        let mut word_start = offset;
+        let word_end = offset + word.len();
+        offset = word_end + 1;
+        if word.is_empty() {
+            continue;
+        }
+        let mut word_cols = word.chars().count();
+        let gap = text[end..word_start].chars().count();
+        if cols > 0 && cols + gap + word_cols > width {
+            lines.push(start..end);
+            cols = 0;
+        }
+        if cols == 0 {
+            start = word_start;
+            // words longer than a line are broken at the width
+            while word_cols > width {
+                let split = word_start + text[word_start..].char_indices().nth(width).unwrap().0;
+                lines.push(word_start..split);
+                word_start = split;
+                start = split;
+                word_cols -= width;
+            }
+            cols = word_cols;
+        } else {
+            cols += gap + word_cols;
+        }
+        end = word_end;
+    }
+    if cols > 0 || lines.is_empty() {
+        lines.push(start..end);
+    }
+    lines
+}
+
+pub enum Link<'a> {
+    Internal(&'a str),
+    External { url: &'a str, label: &'a str },
+}
+
+/// Rewrites `text` for display and finds where each link ended up in it.
+///
+/// Internal `[[Links]]` are kept as written while external `[url label]`
+/// links are shortened to their label.
+pub fn links(text: &str) -> (String, Vec<(Range<usize>, Link<'_>)>) {
+    let mut out = String::new();
+    let mut found = Vec::new();
+    // text before copied is already in out
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(open) = text[search..].find('[') {
+        let open = search + open;
+        let after = &text[open + 1..];
+        search = open + 1;
+        if let Some(inner) = after.strip_prefix('[') {
+            if let Some(close) = inner.find("]]") {
+                let end = open + 2 + close + 2;
+                out.push_str(&text[copied..open]);
+                let start = out.len();
+                out.push_str(&text[open..end]);
+                found.push((start..out.len(), Link::Internal(&inner[..close])));
+                copied = end;
+                search = end;
+            }
+        } else if after.starts_with("http:") || after.starts_with("https:") {
+            let space = after.find(' ');
+            let close = after.find(']');
+            if let (Some(space), Some(close)) = (space, close) {
+                if space < close {
+                    out.push_str(&text[copied..open]);
+                    let start = out.len();
+                    let label = &after[space + 1..close];
+                    out.push_str(label);
+                    let url = &after[..space];
+                    found.push((start..out.len(), Link::External { url, label }));
+                    copied = open + 1 + close + 1;
+                    search = copied;
+                }
+            }
+        }
+    }
+    out.push_str(&text[copied..]);
+    (out, found)
+}
+
+/// Splits `span` across wrapped `lines`, as (line, range within that line) pairs.
+pub fn split_span(span: &Range<usize>, lines: &[Range<usize>]) -> Vec<(usize, Range<usize>)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let start = span.start.max(line.start);
+            let end = span.end.min(line.end);
+            if start < end {
+                Some((i, start - line.start..end - line.start))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(text: &str, width: usize) -> Vec<&str> {
+        wrap(text, width)
+            .into_iter()
+            .map(|line| &text[line])
+            .collect()
+    }
+
+    #[test]
+    fn wrap_breaks_between_words() {
+        assert_eq!(wrapped("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrapped("one  two", 20), vec!["one  two"]);
+    }
+
+    #[test]
+    fn wrap_breaks_long_words_at_the_width() {
+        assert_eq!(wrapped("abcdefgh ij", 3), vec!["abc", "def", "gh", "ij"]);
+    }
+
+    #[test]
+    fn wrap_counts_chars_not_bytes() {
+        assert_eq!(wrapped("café crème", 4), vec!["café", "crèm", "e"]);
+    }
+
+    #[test]
+    fn wrap_keeps_an_empty_line() {
+        assert_eq!(wrapped("", 10), vec![""]);
+        assert_eq!(wrapped("   ", 10), vec![""]);
+    }
+
+    #[test]
+    fn links_keeps_internal_links() {
+        let (text, found) = links("see [[Welcome Visitors]] now");
+        assert_eq!(text, "see [[Welcome Visitors]] now");
+        assert_eq!(found.len(), 1);
+        assert_eq!(&text[found[0].0.clone()], "[[Welcome Visitors]]");
+        assert!(matches!(found[0].1, Link::Internal("Welcome Visitors")));
+    }
+
+    #[test]
+    fn links_shortens_external_links() {
+        let (text, found) = links("go [http://example.com Example] now");
+        assert_eq!(text, "go Example now");
+        assert_eq!(&text[found[0].0.clone()], "Example");
+        assert!(matches!(
+            found[0].1,
+            Link::External {
+                url: "http://example.com",
+                label: "Example"
+            }
+        ));
+    }
+
+    #[test]
+    fn links_leaves_unclosed_brackets() {
+        let (text, found) = links("[[open and [http://example.com");
+        assert_eq!(text, "[[open and [http://example.com");
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn split_span_across_lines() {
+        let lines = vec![0..5, 6..11];
+        assert_eq!(split_span(&(3..8), &lines), vec![(0, 3..5), (1, 0..2)]);
+        assert!(split_span(&(5..6), &lines).is_empty());
+    }
+}
@@ -1,17 +1,73 @@
-use crate::{Ex, ExEventStatus, PageStore, Pane, Wiki};
+use crate::{media, wiki};
+use crate::{
+    Ex, ExEventStatus, Lineup, LinkTarget, Neighborhood, NotFound, Page, PageStore, Pane, Visit,
+    Wiki, WikiPane,
+};
 use anyhow::{anyhow, Error, Result};
 use crossterm::{
-    self,
-    event::{read, Event, KeyCode, KeyModifiers, MouseEvent},
-    ExecutableCommand,
+    self, cursor,
+    event::{
+        read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEvent,
+    },
+    execute,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+    ExecutableCommand, QueueableCommand,
 };
 use serde::{Deserialize, Serialize};
 use std::cmp::{max, min};
 use std::collections::HashMap;
-use std::io::stdout;
-use std::path::PathBuf;
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
 use url::Url;
 
+// paths typed on the ex line may start from the home directory
+fn expand_home(file: &str) -> PathBuf {
+    match (file.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(file), Some(home)) => home.join(file),
+        _ => PathBuf::from(file),
+    }
+}
+
+// the slug a `/view/<slug>` path shows last, as in the web client's lineup urls
+fn view_slug(url: &str) -> Option<&str> {
+    let path = url.strip_prefix("/view/")?;
+    let slug = path.rsplit("/view/").next()?;
+    Some(slug).filter(|slug| !slug.is_empty() && !slug.contains('/'))
+}
+
+// the contents of media from `Terki::resolve_url`
+async fn fetch_media(source: &str) -> Result<Vec<u8>, Error> {
+    if let Some((_, data)) = media::decode_data_url(source) {
+        return Ok(data);
+    }
+    if source.starts_with("http:") || source.starts_with("https:") {
+        let response = reqwest::get(source).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Unable to get {}: {}",
+                source,
+                response.status().as_str()
+            ));
+        }
+        return Ok(response.bytes().await?.to_vec());
+    }
+    Ok(std::fs::read(source)?)
+}
+
+// the narrowest a pane is allowed to get when showing panes side by side
+const MIN_PANE_WIDTH: usize = 60;
+
+// pages are laid out for at least this size, so there's room for the header,
+// the ex line and the prefixes of quotes, lists and code however small the terminal gets
+const MIN_SIZE: (usize, usize) = (20, 3);
+
+fn clamp_size(size: (usize, usize)) -> (usize, usize) {
+    (max(size.0, MIN_SIZE.0), max(size.1, MIN_SIZE.1))
+}
+
 #[derive(Serialize, Deserialize)]
 struct CacheWiki {
     name: String,
@@ -24,13 +80,40 @@ struct CacheWiki {
 struct CachePage {
     wiki: String,
     slug: String,
+    #[serde(default)]
+    scroll_index: usize,
+    #[serde(default)]
+    highlight_index: Option<usize>,
+    // the width the page was wrapped to, which the scroll index depends on
+    #[serde(default)]
+    width: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CacheLineup {
+    Named {
+        name: String,
+        pages: Vec<CachePage>,
+        #[serde(default)]
+        active: usize,
+    },
+    // caches from before lineups had names kept just the pages
+    Unnamed(Vec<CachePage>),
 }
 
 #[derive(Serialize, Deserialize)]
 struct Cache {
     history: Vec<String>,
     wikis: Vec<CacheWiki>,
-    lineups: Vec<Vec<CachePage>>,
+    lineups: Vec<CacheLineup>,
+    #[serde(default)]
+    active_lineup: usize,
+    opener: Option<String>,
+    #[serde(default)]
+    side_by_side: bool,
+    #[serde(default)]
+    edit_mode: bool,
 }
 
 pub enum Location {
@@ -41,28 +124,32 @@ pub enum Location {
 
 pub struct Terki {
     pub wikis: HashMap<String, Wiki>,
-    panes: Vec<Pane>,
-    pane_to_wiki: Vec<String>,
-    pane_to_slug: Vec<String>,
-    active_pane: usize,
+    lineups: Vec<Lineup>,
+    // the lineup on screen
+    lineup: usize,
+    // whether to show as many panes as fit, rather than just the active one
+    side_by_side: bool,
     size: (usize, usize),
     ex: Ex,
     edit_mode: bool,
-    active_line: usize,
+    // command used to open urls, overriding the platform default
+    opener: Option<String>,
+    // sites mentioned by the pages we've opened
+    neighborhood: Neighborhood,
 }
 
 impl Terki {
     pub fn new(size: (usize, usize)) -> Terki {
         Terki {
             wikis: HashMap::new(),
-            panes: Vec::new(),
-            pane_to_wiki: Vec::new(),
-            pane_to_slug: Vec::new(),
-            active_pane: 0,
-            size,
+            lineups: vec![Lineup::new("default")],
+            lineup: 0,
+            side_by_side: false,
+            size: clamp_size(size),
             ex: Ex::new(),
             edit_mode: false,
-            active_line: 0,
+            opener: None,
+            neighborhood: Neighborhood::new(),
         }
     }
 
@@ -81,6 +168,7 @@ impl Terki {
         let contents = std::fs::read_to_string(file)?;
         let cache: Cache = serde_json::from_str(&contents)?;
         for wiki in cache.wikis {
+            self.neighborhood.add(&wiki.name, &wiki.url);
             self.wikis.insert(
                 wiki.name,
                 Wiki::new(PageStore::Http {
@@ -91,13 +179,72 @@ impl Terki {
                 }),
             );
         }
-        for lineup in &cache.lineups {
-            for page in lineup {
-                self.open(&page.wiki, &page.slug, Location::End).await?;
+        self.lineups.clear();
+        let mut skipped = Vec::new();
+        // the saved active lineup, or the nearest one before it that's still around
+        let mut active_lineup = 0;
+        for (i, lineup) in cache.lineups.into_iter().enumerate() {
+            let (name, pages, active) = match lineup {
+                CacheLineup::Named {
+                    name,
+                    pages,
+                    active,
+                } => (name, pages, active),
+                CacheLineup::Unnamed(pages) => ("default".to_string(), pages, 0),
+            };
+            if i <= cache.active_lineup {
+                active_lineup = self.lineups.len();
+            }
+            self.lineups.push(Lineup::new(&name));
+            self.lineup = self.lineups.len() - 1;
+            // the saved active pane, or the nearest one before it that could be reopened
+            let mut active_pane = 0;
+            for (index, page) in pages.into_iter().enumerate() {
+                // pages deleted since, or on a local wiki not passed this time, are left out
+                if self
+                    .open(&page.wiki, &page.slug, Location::End)
+                    .await
+                    .is_err()
+                {
+                    skipped.push(page.slug);
+                    continue;
+                }
+                self.restore_pane(page.scroll_index, page.highlight_index, page.width)?;
+                if index <= active {
+                    active_pane = self.lineup().panes.len() - 1;
+                }
+            }
+            // lineups left with nothing to show, as those of only generated pages are,
+            // get a page saying so, which needs some wiki to belong to
+            if self.lineup().panes.is_empty() {
+                let wiki = match self.wikis.keys().min() {
+                    Some(wiki) => wiki.clone(),
+                    None => {
+                        self.lineups.pop();
+                        active_lineup = active_lineup.min(self.lineups.len().saturating_sub(1));
+                        continue;
+                    }
+                };
+                let page = Page::notice(
+                    name.clone(),
+                    "Nothing in this lineup could be reopened. Use :open to add a page.",
+                );
+                self.wikis.get_mut(&wiki).unwrap().add_ghost("_empty", page);
+                self.open(&wiki, "_empty", Location::End).await?;
             }
-            self.active_pane = 0;
+            self.lineup_mut().active = active_pane;
+        }
+        if self.lineups.is_empty() {
+            self.lineups.push(Lineup::new("default"));
         }
+        self.lineup = active_lineup;
+        self.edit_mode = cache.edit_mode;
         self.ex.history = cache.history;
+        self.opener = cache.opener;
+        self.side_by_side = cache.side_by_side;
+        if !skipped.is_empty() {
+            self.ex.result = format!("Couldn't reopen {}!", skipped.join(", "));
+        }
         Ok(())
     }
 
@@ -128,36 +275,79 @@ impl Terki {
             }
         }
         let mut lineups = Vec::new();
-        // will expand once multiple lineups are supported...
-        let mut lineup = Vec::new();
-        for (i, _pane) in self.panes.iter().enumerate() {
-            lineup.push(CachePage {
-                wiki: self.pane_to_wiki[i].to_owned(),
-                slug: self.pane_to_slug[i].to_owned(),
+        for lineup in &self.lineups {
+            let mut pages = Vec::new();
+            // the active pane, or the nearest one before it that will be reopened
+            let mut active = 0;
+            for (
+                i,
+                WikiPane {
+                    wiki, slug, pane, ..
+                },
+            ) in lineup.panes.iter().enumerate()
+            {
+                // generated pages can't be reopened on the next run
+                if self.wikis[wiki].is_ghost(slug) {
+                    continue;
+                }
+                if i <= lineup.active {
+                    active = pages.len();
+                }
+                pages.push(CachePage {
+                    wiki: wiki.to_owned(),
+                    slug: slug.to_owned(),
+                    scroll_index: pane.scroll_index,
+                    highlight_index: pane.highlight_index,
+                    width: Some(pane.width()),
+                });
+            }
+            lineups.push(CacheLineup::Named {
+                name: lineup.name.to_owned(),
+                pages,
+                active,
             });
         }
-        lineups.push(lineup);
         let cache = Cache {
             wikis,
             lineups,
+            active_lineup: self.lineup,
             history: self.ex.history.to_owned(),
+            opener: self.opener.to_owned(),
+            side_by_side: self.side_by_side,
+            edit_mode: self.edit_mode,
         };
         let cache_file = std::fs::File::create(file)?;
         serde_json::to_writer_pretty(cache_file, &cache)?;
         Ok(())
     }
 
+    fn lineup(&self) -> &Lineup {
+        &self.lineups[self.lineup]
+    }
+
+    fn lineup_mut(&mut self) -> &mut Lineup {
+        &mut self.lineups[self.lineup]
+    }
+
+    fn active(&self) -> &WikiPane {
+        self.lineup().active()
+    }
+
+    fn active_mut(&mut self) -> &mut WikiPane {
+        self.lineup_mut().active_mut()
+    }
+
     fn wiki(&self) -> &Wiki {
-        let wiki = &self.pane_to_wiki[self.active_pane];
+        let wiki = &self.active().wiki;
         self.wikis.get(wiki).unwrap()
     }
 
     fn wiki_mut(&mut self) -> &mut Wiki {
-        let wiki = &self.pane_to_wiki[self.active_pane];
+        let wiki = &self.lineups[self.lineup].active().wiki;
         self.wikis.get_mut(wiki).unwrap()
     }
 
-    pub fn add_local<'a>(&'a mut self, path: PathBuf) -> Option<&'a mut Wiki> {
+    pub fn add_local(&mut self, path: PathBuf) -> Option<&mut Wiki> {
         if !path.exists() {
             return None;
         }
@@ -181,8 +371,8 @@ impl Terki {
     }
 
     pub fn add_remote(&mut self, url: &str) -> Result<String, Error> {
-        let parsed = Url::parse(url)?;
-        let host = parsed.host_str().ok_or(anyhow!("No host in url!"))?;
+        let host = wiki::host(&Url::parse(url)?).ok_or(anyhow!("No host in url!"))?;
+        self.neighborhood.add(&host, url);
         self.wikis.insert(
             host.to_owned(),
             Wiki::new(PageStore::Http {
@@ -202,32 +392,57 @@ impl Terki {
             .ok_or(anyhow!("wiki not found: {}", wiki))?;
         let page = wiki_obj.page(slug).await?;
         let pane = Pane::new(page.lines(self.size.0), self.size);
-        // Ug... Might be better to just wrap everything in a WikiPane
-        match (self.panes.len(), location) {
+        let sites = page.sites();
+        // neighbors are assumed to be served the same way as the page that mentions them
+        let scheme = match &wiki_obj.store {
+            PageStore::Http { url, .. } => Url::parse(url)?.scheme().to_owned(),
+            PageStore::Local { .. } => "http".to_string(),
+        };
+        for site in sites {
+            let url = format!("{}://{}/", scheme, site);
+            self.neighborhood.add(&site, &url);
+        }
+        let pane = WikiPane::new(wiki, slug, pane);
+        let lineup = self.lineup_mut();
+        match (lineup.panes.len(), location) {
             (0, _) | (_, Location::End) => {
-                self.panes.push(pane);
-                self.pane_to_wiki.push(wiki.to_owned());
-                self.pane_to_slug.push(slug.to_owned());
-                self.active_pane = self.panes.len() - 1;
+                lineup.panes.push(pane);
+                lineup.active = lineup.panes.len() - 1;
             }
             (_, Location::Replace) => {
-                self.panes.remove(self.active_pane);
-                self.pane_to_wiki.remove(self.active_pane);
-                self.pane_to_slug.remove(self.active_pane);
-                self.panes.insert(self.active_pane, pane);
-                self.pane_to_wiki.insert(self.active_pane, wiki.to_owned());
-                self.pane_to_slug.insert(self.active_pane, slug.to_owned());
+                let old = std::mem::replace(&mut lineup.panes[lineup.active], pane);
+                lineup.active_mut().replaces(old);
             }
             (_, Location::Next) => {
-                self.active_pane += 1;
-                self.panes.insert(self.active_pane, pane);
-                self.pane_to_wiki.insert(self.active_pane, wiki.to_owned());
-                self.pane_to_slug.insert(self.active_pane, slug.to_owned());
+                lineup.active += 1;
+                lineup.panes.insert(lineup.active, pane);
             }
         };
         Ok(())
     }
 
+    /// Whether the lineup on screen has a page from the given wiki.
+    pub fn shows(&self, wiki: &str) -> bool {
+        self.lineup().panes.iter().any(|pane| pane.wiki == wiki)
+    }
+
+    // puts the active pane's page back where it was left
+    fn restore_pane(
+        &mut self,
+        scroll_index: usize,
+        highlight_index: Option<usize>,
+        width: Option<usize>,
+    ) -> Result<(), Error> {
+        let index = self.lineup().active;
+        // scroll indexes only make sense at the width they were saved at
+        if let Some(width) = width {
+            self.place_pane(index, 0, width, false)?;
+        }
+        self.active_mut()
+            .pane
+            .restore(scroll_index, highlight_index)
+    }
+
     pub async fn display(
         &mut self,
         wiki: &str,
@@ -238,19 +453,49 @@ impl Terki {
         self.display_active_pane()
     }
 
+    // opens the page after the active pane, closing pages off to the right
+    async fn display_next(&mut self, wiki: &str, slug: &str) -> Result<(), Error> {
+        // retrieve first so a missing page leaves the lineup alone
+        self.wikis
+            .get_mut(wiki)
+            .ok_or_else(|| anyhow!("wiki not found: {}", wiki))?
+            .page(slug)
+            .await?;
+        let lineup = self.lineup_mut();
+        let next_pane = lineup.active + 1;
+        lineup.panes.truncate(next_pane + 1);
+        // the page takes over the pane to the right, so what it showed can be gone back to
+        if lineup.panes.len() > next_pane {
+            lineup.active = next_pane;
+            self.display(wiki, slug, Location::Replace).await
+        } else {
+            self.display(wiki, slug, Location::Next).await
+        }
+    }
+
     fn scroll_down(&mut self, scroll_by: usize) -> Result<(), Error> {
-        self.panes[self.active_pane].scroll_down(scroll_by)?;
+        self.active_mut().pane.scroll_down(scroll_by)?;
         Ok(())
     }
 
     fn scroll_up(&mut self, scroll_by: usize) -> Result<(), Error> {
-        self.panes[self.active_pane].scroll_up(scroll_by)?;
+        self.active_mut().pane.scroll_up(scroll_by)?;
         Ok(())
     }
 
     async fn run_command(&mut self, command: &str) -> Result<(), Error> {
+        // report failures on the ex line rather than exiting
+        if let Err(err) = self.execute(command).await {
+            self.ex.result = format!("Error: {}", err);
+            self.ex.display(self.size.1 as u16 - 1)?;
+            self.display_active_pane()?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&mut self, command: &str) -> Result<(), Error> {
         let parts = shell_words::split(command)?;
-        if parts.len() == 0 {
+        if parts.is_empty() {
             // err, no command specified
             return Ok(());
         }
@@ -276,52 +521,365 @@ impl Terki {
             }
             "web" => match &self.wiki().store {
                 PageStore::Http { url, .. } => {
-                    let slug = &self.pane_to_slug[self.active_pane];
-                    let mut command = std::process::Command::new("cmd");
-                    command.args(&["/c", "start", &format!("{}/view/{}", url, slug)]);
-                    let mut process = command.spawn()?;
-                    let result = process.wait()?;
-                    if result.success() {
-                        self.ex.result = "Opening page in web browser...".to_string();
-                    }
-                    // mouse capture gets disabled after running an external command
-                    // not sure why... the workaround is to re-enable it
-                    stdout().execute(crossterm::event::EnableMouseCapture)?;
+                    let slug = &self.active().slug;
+                    let url = format!("{}/view/{}", url, slug);
+                    self.open_url(&url)?;
+                    self.ex.result = "Opening page in web browser...".to_string();
                 }
                 _ => self.ex.result = "URLs are not known for local wikis!".to_string(),
             },
+            "opener" => {
+                if parts.len() < 2 {
+                    self.ex.result = format!("Opener: {}", self.opener());
+                } else {
+                    self.opener = Some(parts[1..].join(" "));
+                    self.ex.result = "Opener set!".to_string();
+                }
+            }
             "reload" => {
                 self.ex.result = self.reload_active_pane().await?;
             }
+            "edit" | "add" | "remove" | "move" => {
+                self.ex.result = self.change_highlighted(command, &parts[1..]).await?;
+            }
+            "editor" => {
+                self.ex.result = self.edit_in_editor().await?;
+            }
             "open" => {
-                if parts.len() < 2 {
+                let mut args: &[String] = &parts[1..parts.len()];
+                let at_end = args.len() > 1 && args[0] == "end";
+                if at_end {
+                    args = &args[1..];
+                }
+                let slug = match args.first() {
+                    Some(slug) => slug.clone(),
                     // err, not enough args
+                    None => return Ok(()),
+                };
+                // links pass their text along to title the page if it's missing
+                let title = args.get(1).cloned().unwrap_or_else(|| wiki::title(&slug));
+                let wiki = self.active().wiki.clone();
+                let result = if at_end {
+                    self.display(&wiki, &slug, Location::End).await
+                } else {
+                    self.display_next(&wiki, &slug).await
+                };
+                match result {
+                    Err(err) if err.downcast_ref::<NotFound>().is_some() => {
+                        if let Some(site) = self.open_in_neighborhood(&wiki, &slug, at_end).await? {
+                            self.ex.result = format!("Found {} on {}!", slug, site);
+                            return Ok(());
+                        }
+                        // offer to create missing pages, as following a link does in the web client,
+                        // at the slug asked for even when the title wouldn't give it back
+                        let quoted = shell_words::quote(&title).into_owned();
+                        let prompt = if wiki::slug(&title) == slug {
+                            format!("create {}", quoted)
+                        } else {
+                            format!("create slug {} {}", shell_words::quote(&slug), quoted)
+                        };
+                        self.ex
+                            .activate_with_prompt(self.size.1 as u16 - 1, prompt)?;
+                    }
+                    result => result?,
+                }
+            }
+            "visit" => {
+                let mut args: &[String] = &parts[1..parts.len()];
+                let at_end = args.len() > 2 && args[0] == "end";
+                if at_end {
+                    args = &args[1..];
+                }
+                if args.len() != 2 {
+                    self.ex.result = "Usage: visit [end] <site> <slug>".to_string();
                     return Ok(());
                 }
-                let args: &[String] = &parts[1..parts.len()];
-                if args.len() == 1 {
-                    let wiki = self.pane_to_wiki[self.active_pane].clone();
-
-                    // Close pages off to the right
-                    let next_pane = self.active_pane + 1;
-                    self.pane_to_wiki.truncate(next_pane);
-                    self.pane_to_slug.truncate(next_pane);
-                    self.panes.truncate(next_pane);
-
-                    self.display(&wiki, &args[0], Location::Next).await?;
-                } else if args.len() == 2 && args[0] == "end" {
-                    let wiki = self.pane_to_wiki[self.active_pane].clone();
+                let wiki = self.add_site(&args[0])?;
+                if at_end {
                     self.display(&wiki, &args[1], Location::End).await?;
+                } else {
+                    self.display_next(&wiki, &args[1]).await?;
                 }
             }
-            "close" => {
-                if self.panes.len() > 1 {
-                    self.panes.remove(self.active_pane);
-                    if self.active_pane >= self.panes.len() {
-                        self.active_pane = self.panes.len() - 1;
+            "create" => {
+                let mut args: &[String] = &parts[1..];
+                let slug = if args.len() > 2 && args[0] == "slug" {
+                    let slug = args[1].clone();
+                    args = &args[2..];
+                    Some(slug)
+                } else {
+                    None
+                };
+                let title = match args.first() {
+                    Some(title) => title.clone(),
+                    None => {
+                        self.ex.result =
+                            "Usage: create [slug <slug>] <title> [template]".to_string();
+                        return Ok(());
+                    }
+                };
+                let wiki = self.active().wiki.clone();
+                let slug = slug.unwrap_or_else(|| wiki::slug(&title));
+                let template = args.get(1).map(String::as_str);
+                self.wiki_mut().create(&slug, &title, template).await?;
+                self.display_next(&wiki, &slug).await?;
+                self.ex.result = format!("Created {}!", slug);
+            }
+            "sitemap" => {
+                let by_date = match parts.get(1).map(String::as_str) {
+                    None | Some("name") => false,
+                    Some("date") => true,
+                    Some(_) => {
+                        self.ex.result = "Usage: sitemap [name|date]".to_string();
+                        return Ok(());
+                    }
+                };
+                let wiki = self.active().wiki.clone();
+                let mut entries = self.wiki_mut().sitemap().await?.to_vec();
+                if by_date {
+                    // newest first
+                    entries.sort_by_key(|entry| std::cmp::Reverse(entry.date));
+                } else {
+                    entries.sort_by_key(|entry| entry.title.to_lowercase());
+                }
+                let page = Page::sitemap(format!("Sitemap of {}", wiki), &wiki, &entries);
+                self.wiki_mut().add_ghost("_sitemap", page);
+                self.display_next(&wiki, "_sitemap").await?;
+            }
+            "recent" => {
+                let mut pages = Vec::new();
+                let mut sites: Vec<String> = self.wikis.keys().cloned().collect();
+                sites.sort();
+                for site in &sites {
+                    // one unreachable wiki shouldn't hide the others' changes
+                    if let Ok(sitemap) = self.wikis.get_mut(site).unwrap().refresh_sitemap().await {
+                        pages.extend(sitemap.iter().map(|entry| (site.clone(), entry.clone())));
+                    }
+                }
+                for site in self.neighborhood.sites() {
+                    if sites.contains(site) {
+                        continue;
+                    }
+                    if let Some(sitemap) = self.neighborhood.sitemap(site) {
+                        pages.extend(sitemap.into_iter().map(|entry| (site.clone(), entry)));
+                    }
+                }
+                let wiki = self.active().wiki.clone();
+                let page = Page::recent("Recent Changes".to_string(), &pages);
+                self.wiki_mut().add_ghost("_recent-changes", page);
+                self.display_next(&wiki, "_recent-changes").await?;
+            }
+            "search" => {
+                let query = parts[1..].join(" ");
+                if query.is_empty() {
+                    self.ex.result = "Usage: search <terms>".to_string();
+                    return Ok(());
+                }
+                self.add_local_neighbors().await?;
+                let hits = self.neighborhood.search(&query);
+                if hits.is_empty() {
+                    self.ex.result = format!("Nothing found for {}!", query);
+                    return Ok(());
+                }
+                let wiki = self.active().wiki.clone();
+                let title = format!("Search for {}", query);
+                let page = Page::references(title, &hits);
+                self.wiki_mut().add_ghost("_search", page);
+                self.display_next(&wiki, "_search").await?;
+                // so a single Enter opens the first hit
+                self.active_mut().pane.focus_link(true)?;
+                self.ex.result = format!("Found {} pages!", hits.len());
+            }
+            "neighborhood" => {
+                self.add_local_neighbors().await?;
+                let wiki = self.active().wiki.clone();
+                let neighbors = self.neighborhood.neighbors();
+                let page = Page::neighborhood("Neighborhood".to_string(), &neighbors);
+                self.wiki_mut().add_ghost("_neighborhood", page);
+                self.display_next(&wiki, "_neighborhood").await?;
+            }
+            "write" => {
+                let file = match parts.get(1) {
+                    Some(file) => file,
+                    None => {
+                        self.ex.result = "Usage: write <file>".to_string();
+                        return Ok(());
                     }
+                };
+                let file = expand_home(file);
+                let index = self.target_item(&["code"]).await?;
+                let page = self.page().await?;
+                let text = index
+                    .and_then(|index| page.text(index))
+                    .ok_or_else(|| anyhow!("No item to write!"))?;
+                std::fs::write(&file, text)?;
+                self.ex.result = format!("Wrote {}!", file.display());
+            }
+            "media" => {
+                let wiki = self.active().wiki.clone();
+                let slug = self.active().slug.clone();
+                let index = self.target_item(&["image", "video", "audio"]).await?;
+                let page = self.page().await?;
+                let url = index
+                    .and_then(|index| page.media_url(index))
+                    .ok_or_else(|| anyhow!("No media to open!"))?;
+                let source = self.resolve_url(&wiki, &url)?;
+                match parts.get(1) {
+                    Some(file) => {
+                        let file = expand_home(file);
+                        std::fs::write(&file, fetch_media(&source).await?)?;
+                        self.ex.result = format!("Saved {}!", file.display());
+                    }
+                    // embedded media has to be written out before anything can open it
+                    None => match media::decode_data_url(&source) {
+                        Some((mime, data)) => {
+                            let name = format!("terki-{}.{}", slug, media::extension(&mime));
+                            let file = std::env::temp_dir().join(name);
+                            std::fs::write(&file, data)?;
+                            self.open_url(&file.to_string_lossy())?;
+                        }
+                        None => self.open_url(&source)?,
+                    },
                 }
             }
+            "csv" => {
+                let file = match parts.get(1) {
+                    Some(file) => expand_home(file),
+                    None => {
+                        self.ex.result = "Usage: csv <file>".to_string();
+                        return Ok(());
+                    }
+                };
+                let index = self.target_item(&["data"]).await?;
+                let page = self.page().await?;
+                let csv = index
+                    .and_then(|index| page.csv(index))
+                    .ok_or_else(|| anyhow!("No data to export!"))?;
+                std::fs::write(&file, csv)?;
+                self.ex.result = format!("Wrote {}!", file.display());
+            }
+            "history" => {
+                let wiki = self.active().wiki.clone();
+                let slug = self.active().slug.clone();
+                let history_slug = format!("{}_history", slug);
+                let wiki_obj = self.wiki_mut();
+                let history = wiki_obj.page(&slug).await?.history();
+                wiki_obj.add_ghost(&history_slug, history);
+                self.display_next(&wiki, &history_slug).await?;
+                // start on the first entry so :revision can pick one straight away
+                let pane = &mut self.active_mut().pane;
+                pane.highlight_display_line(0)?;
+                pane.display()?;
+            }
+            "revision" => {
+                let wiki = self.active().wiki.clone();
+                let slug = self.active().slug.clone();
+                // from a history pane, default to the highlighted entry
+                let (slug, revision) = match slug.strip_suffix("_history") {
+                    Some(source) => match (parts.get(1), self.highlighted_item()) {
+                        (Some(revision), _) => (source.to_owned(), revision.parse()?),
+                        (None, Some(index)) => (source.to_owned(), index + 1),
+                        (None, None) => {
+                            self.ex.result = "Highlight a journal entry first!".to_string();
+                            return Ok(());
+                        }
+                    },
+                    None => match parts.get(1) {
+                        Some(revision) => (slug, revision.parse()?),
+                        None => {
+                            self.ex.result = "Usage: revision <n>".to_string();
+                            return Ok(());
+                        }
+                    },
+                };
+                let revision_slug = format!("{}_rev{}", slug, revision);
+                let wiki_obj = self.wiki_mut();
+                let page = wiki_obj.page(&slug).await?.revision(revision)?;
+                wiki_obj.add_ghost(&revision_slug, page);
+                self.display_next(&wiki, &revision_slug).await?;
+            }
+            "diff" => {
+                let against = match parts.get(1) {
+                    Some(against) => against.clone(),
+                    None => {
+                        self.ex.result = "Usage: diff <revision>|<wiki>".to_string();
+                        return Ok(());
+                    }
+                };
+                let wiki = self.active().wiki.clone();
+                let slug = self.active().slug.clone();
+                let diff_slug = format!("{}_diff", slug);
+                let page = self.wiki_mut().page(&slug).await?;
+                let diff = if let Ok(revision) = against.parse() {
+                    let older = page.revision(revision)?;
+                    let title = format!("{} since revision {}", page.title(), revision);
+                    page.diff(&older, title)
+                } else {
+                    self.wikis
+                        .get_mut(&against)
+                        .ok_or_else(|| anyhow!("wiki not found: {}", against))?
+                        .page(&slug)
+                        .await?;
+                    let page = self.wikis[&wiki].cached(&slug).unwrap();
+                    let other = self.wikis[&against].cached(&slug).unwrap();
+                    let title = format!("{} on {} vs {}", page.title(), against, wiki);
+                    page.diff(other, title)
+                };
+                self.wiki_mut().add_ghost(&diff_slug, diff);
+                self.display_next(&wiki, &diff_slug).await?;
+            }
+            "fork" => {
+                let target = match parts.get(1) {
+                    Some(target) => target.clone(),
+                    None => {
+                        self.ex.result = "Usage: fork <wiki>".to_string();
+                        return Ok(());
+                    }
+                };
+                let wiki = self.active().wiki.clone();
+                let slug = self.active().slug.clone();
+                if target == wiki {
+                    self.ex.result = format!("{} is already on {}!", slug, wiki);
+                } else {
+                    let page = self.wiki_mut().page(&slug).await?.clone();
+                    let site = self.wiki().store.host();
+                    self.wikis
+                        .get_mut(&target)
+                        .ok_or_else(|| anyhow!("wiki not found: {}", target))?
+                        .fork(&slug, page, site)
+                        .await?;
+                    // like the web client, the fork takes the original's place
+                    self.display(&target, &slug, Location::Replace).await?;
+                    self.ex.result = format!("Forked {} from {} to {}!", slug, wiki, target);
+                }
+            }
+            "layout" => {
+                self.side_by_side = match parts.get(1).map(String::as_str) {
+                    None => !self.side_by_side,
+                    Some("single") => false,
+                    Some("side") => true,
+                    Some(_) => {
+                        self.ex.result = "Usage: layout [single|side]".to_string();
+                        return Ok(());
+                    }
+                };
+                self.ex.result = if self.side_by_side {
+                    "Showing panes side by side!".to_string()
+                } else {
+                    "Showing one pane at a time!".to_string()
+                };
+            }
+            "close" => {
+                if !self.lineup_mut().close() {
+                    self.ex.result = "Can't close the last pane!".to_string();
+                }
+            }
+            "lineup" => {
+                self.ex.result = self.change_lineup(&parts[1..]).await?;
+            }
+            "back" | "forward" => {
+                self.ex.result = self.travel(command == "forward").await?;
+            }
             _ => {
                 // err, unrecognized command
                 return Ok(());
@@ -333,10 +891,9 @@ impl Terki {
     }
 
     async fn reload_active_pane(&mut self) -> Result<String, Error> {
-        // the clones are yet more reason to merge the vecs into a single datastructure
-        let wiki = self.pane_to_wiki[self.active_pane].clone();
+        let WikiPane { wiki, slug, .. } = self.active();
+        let (wiki, slug) = (wiki.clone(), slug.clone());
         let store = &mut self.wikis.get_mut(&wiki).unwrap().store;
-        let slug = self.pane_to_slug[self.active_pane].clone();
         match store {
             PageStore::Http { cache, .. } => {
                 cache.remove(&slug);
@@ -347,65 +904,630 @@ impl Terki {
         }
     }
 
+    fn opener(&self) -> String {
+        match &self.opener {
+            Some(opener) => opener.to_owned(),
+            None if cfg!(windows) => "cmd /c start".to_string(),
+            None if cfg!(target_os = "macos") => "open".to_string(),
+            None => "xdg-open".to_string(),
+        }
+    }
+
+    // hands the terminal over to an external command, redrawing once it's back
+    fn run_suspended(
+        &mut self,
+        command: &mut std::process::Command,
+    ) -> Result<std::process::ExitStatus, Error> {
+        let mut stdout = stdout();
+        execute!(stdout, LeaveAlternateScreen, DisableMouseCapture)?;
+        disable_raw_mode()?;
+        let status = command.status();
+        enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        self.display_active_pane()?;
+        Ok(status?)
+    }
+
+    // edits the given file in $EDITOR
+    fn run_editor(&mut self, file: &Path) -> Result<(), Error> {
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+        let mut args = shell_words::split(&editor)?;
+        if args.is_empty() {
+            return Err(anyhow!("$EDITOR is empty!"));
+        }
+        let mut command = std::process::Command::new(args.remove(0));
+        command.args(&args).arg(file);
+        if !self.run_suspended(&mut command)?.success() {
+            return Err(anyhow!("{} exited with an error!", editor));
+        }
+        Ok(())
+    }
+
+    fn open_url(&mut self, url: &str) -> Result<(), Error> {
+        let mut args = shell_words::split(&self.opener())?;
+        if args.is_empty() {
+            return Err(anyhow!("No opener set!"));
+        }
+        let mut command = std::process::Command::new(args.remove(0));
+        command.args(&args).arg(url);
+        if !self.run_suspended(&mut command)?.success() {
+            return Err(anyhow!("Unable to open {}", url));
+        }
+        Ok(())
+    }
+
+    // opens a link from the active pane's links table
+    async fn follow_link(&mut self, link: usize, at_end: bool) -> Result<(), Error> {
+        let (text, target) = match self.active().pane.link(link) {
+            Some(link) => link.clone(),
+            None => return Ok(()),
+        };
+        let command = if at_end { "open end" } else { "open" };
+        let command = match target {
+            // html items can link to pages on their own wiki by path
+            LinkTarget::Url(url) if view_slug(&url).is_some() => format!(
+                "{} {} {}",
+                command,
+                shell_words::quote(view_slug(&url).unwrap()),
+                shell_words::quote(&text)
+            ),
+            LinkTarget::Url(url) => {
+                let wiki = self.active().wiki.clone();
+                let url = self.resolve_url(&wiki, &url)?;
+                self.ex.result = match self.open_url(&url) {
+                    Ok(()) => format!("Opening {}...", url),
+                    Err(err) => format!("Error: {}", err),
+                };
+                return self.ex.display(self.size.1 as u16 - 1);
+            }
+            LinkTarget::Slug(slug) => format!(
+                "{} {} {}",
+                command,
+                shell_words::quote(&slug),
+                shell_words::quote(&text)
+            ),
+            LinkTarget::Site { site, slug } => format!(
+                "visit {}{} {}",
+                if at_end { "end " } else { "" },
+                shell_words::quote(&site),
+                shell_words::quote(&slug)
+            ),
+        };
+        self.run_command(&command).await
+    }
+
+    // local wikis have no sitemap to fetch in the background, so theirs are read as needed
+    async fn add_local_neighbors(&mut self) -> Result<(), Error> {
+        let local: Vec<String> = self
+            .wikis
+            .iter()
+            .filter(|(_, wiki)| matches!(wiki.store, PageStore::Local { .. }))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in local {
+            let entries = self.wikis.get_mut(&name).unwrap().sitemap().await?.to_vec();
+            self.neighborhood.add_sitemap(&name, entries);
+        }
+        Ok(())
+    }
+
+    // the wiki for a site named by a reference, registering it if needed
+    fn add_site(&mut self, site: &str) -> Result<String, Error> {
+        if self.wikis.contains_key(site) {
+            return Ok(site.to_owned());
+        }
+        // assume the site is served the same way as the one we're on
+        let scheme = match &self.wiki().store {
+            PageStore::Http { url, .. } => Url::parse(url)?.scheme().to_owned(),
+            PageStore::Local { .. } => "http".to_string(),
+        };
+        self.add_remote(&format!("{}://{}", scheme, site))
+    }
+
+    // completes the slug being typed after `open`, matching slugs or titles,
+    // or the name of a lineup to switch to or delete
+    async fn complete(&mut self, buffer: &str) {
+        let lineup = buffer
+            .strip_prefix("lineup switch ")
+            .or_else(|| buffer.strip_prefix("lineup delete "));
+        if let Some(typed) = lineup {
+            let completions = self
+                .lineups
+                .iter()
+                .map(|lineup| lineup.name.clone())
+                .filter(|name| name.starts_with(typed))
+                .collect();
+            self.ex.complete(buffer.len() - typed.len(), completions);
+            return;
+        }
+        let typed = match buffer
+            .strip_prefix("open end ")
+            .or_else(|| buffer.strip_prefix("open "))
+        {
+            Some(typed) => typed,
+            None => return,
+        };
+        // lowercasing can change the length, so only the match uses it
+        let start = buffer.len() - typed.len();
+        let typed = typed.to_lowercase();
+        // without a sitemap there's just nothing to offer
+        let sitemap = match self.wiki_mut().sitemap().await {
+            Ok(sitemap) => sitemap,
+            Err(_) => return,
+        };
+        let mut completions: Vec<String> = sitemap
+            .iter()
+            .filter(|entry| {
+                entry.slug.starts_with(&typed) || entry.title.to_lowercase().starts_with(&typed)
+            })
+            .map(|entry| entry.slug.clone())
+            .collect();
+        completions.sort();
+        self.ex.complete(start, completions);
+    }
+
+    // opens the page from the first neighbor that has it, returning that neighbor
+    async fn open_in_neighborhood(
+        &mut self,
+        wiki: &str,
+        slug: &str,
+        at_end: bool,
+    ) -> Result<Option<String>, Error> {
+        for site in self.neighborhood.find(slug) {
+            if site == wiki {
+                continue;
+            }
+            let site_wiki = self.add_site(&site)?;
+            let result = if at_end {
+                self.display(&site_wiki, slug, Location::End).await
+            } else {
+                self.display_next(&site_wiki, slug).await
+            };
+            match result {
+                // the sitemap was stale, so keep looking
+                Err(err) if err.downcast_ref::<NotFound>().is_some() => continue,
+                result => result?,
+            }
+            return Ok(Some(site));
+        }
+        Ok(None)
+    }
+
+    // media and link urls may be relative to the wiki they're on, which can be a local directory
+    fn resolve_url(&self, wiki: &str, url: &str) -> Result<String, Error> {
+        if url.starts_with("data:") || Url::parse(url).is_ok() {
+            return Ok(url.to_owned());
+        }
+        Ok(match &self.wikis[wiki].store {
+            PageStore::Http { url: base, .. } => Url::parse(base)?.join(url)?.to_string(),
+            PageStore::Local { path } => path
+                .join(url.trim_start_matches('/'))
+                .to_string_lossy()
+                .into_owned(),
+        })
+    }
+
+    // the active pane's page
+    async fn page(&mut self) -> Result<&mut Page, Error> {
+        let wiki = self.active().wiki.clone();
+        let slug = self.active().slug.clone();
+        self.wikis.get_mut(&wiki).unwrap().page(&slug).await
+    }
+
+    // the highlighted item, or else the first item of one of the given types
+    async fn target_item(&mut self, types: &[&str]) -> Result<Option<usize>, Error> {
+        let pane = &self.active().pane;
+        let highlighted = pane.highlighted_line().and_then(|line| pane.item_at(line));
+        let page = self.page().await?;
+        let first = types.iter().filter_map(|kind| page.find_item(kind)).min();
+        Ok(highlighted.or(first))
+    }
+
+    // scrolls the target data item's table sideways
+    async fn scroll_columns(&mut self, by: isize) -> Result<(), Error> {
+        if let Some(index) = self.target_item(&["data"]).await? {
+            if self.page().await?.scroll_columns(index, by) {
+                self.redraw_active_pane(index).await?;
+                self.active_mut().pane.display()?;
+            }
+        }
+        Ok(())
+    }
+
+    // the story index of the highlighted item on the active pane;
+    // read-only panes can be picked from without entering edit mode
+    fn highlighted_item(&self) -> Option<usize> {
+        let WikiPane {
+            wiki, slug, pane, ..
+        } = self.active();
+        match pane.highlighted_line() {
+            Some(line) if self.edit_mode || self.wikis[wiki].is_ghost(slug) => pane.item_at(line),
+            _ => None,
+        }
+    }
+
+    // re-renders the active pane after a change, moving any highlight to the target item
+    async fn redraw_active_pane(&mut self, target: usize) -> Result<(), Error> {
+        let wiki = self.active().wiki.clone();
+        let slug = self.active().slug.clone();
+        let width = self.active_mut().pane.width();
+        let page = self.wikis.get_mut(&wiki).unwrap().page(&slug).await?;
+        let lines = page.lines(width);
+        let edit_mode = self.edit_mode;
+        let pane = &mut self.active_mut().pane;
+        // an empty page in edit mode has nothing highlighted until its first item is added
+        let highlighted = pane.highlight_index.is_some() || edit_mode;
+        pane.set_lines(lines)?;
+        if let Some(line) = pane.item_line(target).filter(|_| highlighted) {
+            pane.highlight_display_line(line)?;
+        }
+        Ok(())
+    }
+
+    async fn change_highlighted(
+        &mut self,
+        command: &str,
+        args: &[String],
+    ) -> Result<String, Error> {
+        let text = args.join(" ");
+        if (command == "edit" || command == "add") && text.is_empty() {
+            return Ok(format!("Usage: {} <text>", command));
+        }
+        let slug = self.active().slug.clone();
+        let empty = self.active().pane.is_empty();
+        let index = match self.highlighted_item() {
+            Some(index) => index,
+            // adding to an empty page starts its story
+            None if command == "add" && self.edit_mode && empty => {
+                self.wiki_mut().add(&slug, None, text).await?;
+                self.redraw_active_pane(0).await?;
+                return Ok(format!("Saved {}!", slug));
+            }
+            None => return Ok("Highlight an item in edit mode first!".to_string()),
+        };
+        let wiki = self.wiki_mut();
+        // the item to highlight once the page is redrawn
+        let target = match command {
+            "edit" => {
+                wiki.edit(&slug, index, text).await?;
+                index
+            }
+            "add" => {
+                wiki.add(&slug, Some(index), text).await?;
+                index + 1
+            }
+            "remove" => {
+                wiki.remove(&slug, index).await?;
+                index
+            }
+            _ => {
+                let last = wiki.page(&slug).await?.item_count().saturating_sub(1);
+                let to = match args.first().map(String::as_str) {
+                    Some("up") if index == 0 => return Ok("Already at the top!".to_string()),
+                    Some("up") => index - 1,
+                    Some("down") if index >= last => {
+                        return Ok("Already at the bottom!".to_string())
+                    }
+                    Some("down") => index + 1,
+                    _ => return Ok("Usage: move up|down".to_string()),
+                };
+                wiki.move_item(&slug, index, to).await?;
+                to
+            }
+        };
+        self.redraw_active_pane(target).await?;
+        Ok(format!("Saved {}!", slug))
+    }
+
+    // shows the page the active pane showed before (or after) this one
+    async fn travel(&mut self, forward: bool) -> Result<String, Error> {
+        let WikiPane {
+            back,
+            forward: ahead,
+            ..
+        } = self.active();
+        let (mut back, mut ahead) = (back.clone(), ahead.clone());
+        // going forward is going back with the stacks swapped
+        let (from, to) = if forward {
+            (&mut ahead, &mut back)
+        } else {
+            (&mut back, &mut ahead)
+        };
+        let visit = match from.pop() {
+            Some(visit) => visit,
+            None if forward => return Ok("Nothing to go forward to!".to_string()),
+            None => return Ok("Nothing to go back to!".to_string()),
+        };
+        to.push(self.active().visit());
+        let Visit {
+            wiki,
+            slug,
+            scroll_index,
+            highlight_index,
+            width,
+        } = visit;
+        self.open(&wiki, &slug, Location::Replace).await?;
+        let wiki_pane = self.active_mut();
+        wiki_pane.back = back;
+        wiki_pane.forward = ahead;
+        self.restore_pane(scroll_index, highlight_index, Some(width))?;
+        Ok(String::new())
+    }
+
+    fn find_lineup(&self, name: &str) -> Option<usize> {
+        self.lineups.iter().position(|lineup| lineup.name == name)
+    }
+
+    // lists, creates, names, switches between and deletes lineups
+    async fn change_lineup(&mut self, args: &[String]) -> Result<String, Error> {
+        let name = args.get(1).cloned();
+        match (args.first().map(String::as_str), name) {
+            (None, _) => {
+                let names: Vec<String> = self
+                    .lineups
+                    .iter()
+                    .enumerate()
+                    .map(|(i, lineup)| {
+                        if i == self.lineup {
+                            format!("*{}", lineup.name)
+                        } else {
+                            lineup.name.clone()
+                        }
+                    })
+                    .collect();
+                Ok(format!("Lineups: {}", names.join(" ")))
+            }
+            (Some("new"), Some(name)) => {
+                if self.find_lineup(&name).is_some() {
+                    return Ok(format!("Lineup {} already exists!", name));
+                }
+                // a new lineup starts from the page we're on
+                let WikiPane { wiki, slug, .. } = self.active();
+                let (wiki, slug) = (wiki.clone(), slug.clone());
+                self.lineups.push(Lineup::new(&name));
+                self.lineup = self.lineups.len() - 1;
+                self.open(&wiki, &slug, Location::End).await?;
+                Ok(format!("Created lineup {}!", name))
+            }
+            (Some("name"), Some(name)) => {
+                if self.find_lineup(&name).is_some() {
+                    return Ok(format!("Lineup {} already exists!", name));
+                }
+                self.lineup_mut().name = name.clone();
+                Ok(format!("Named lineup {}!", name))
+            }
+            (Some("switch"), Some(name)) => match self.find_lineup(&name) {
+                Some(index) => {
+                    self.lineup = index;
+                    Ok(format!("Switched to lineup {}!", name))
+                }
+                None => Ok(format!("No lineup named {}!", name)),
+            },
+            (Some("delete"), name) => {
+                let index = match name {
+                    Some(name) => match self.find_lineup(&name) {
+                        Some(index) => index,
+                        None => return Ok(format!("No lineup named {}!", name)),
+                    },
+                    None => self.lineup,
+                };
+                if self.lineups.len() < 2 {
+                    return Ok("Can't delete the last lineup!".to_string());
+                }
+                let deleted = self.lineups.remove(index);
+                if index < self.lineup || self.lineup == self.lineups.len() {
+                    self.lineup -= 1;
+                }
+                Ok(format!("Deleted lineup {}!", deleted.name))
+            }
+            _ => Ok("Usage: lineup [new|name|switch|delete] <name>".to_string()),
+        }
+    }
+
+    async fn edit_in_editor(&mut self) -> Result<String, Error> {
+        let index = match self.highlighted_item() {
+            Some(index) => index,
+            None => return Ok("Highlight an item in edit mode first!".to_string()),
+        };
+        let slug = self.active().slug.clone();
+        let text = self
+            .wiki_mut()
+            .page(&slug)
+            .await?
+            .text(index)
+            .unwrap_or("")
+            .to_owned();
+        let file = std::env::temp_dir().join(format!("terki-{}-{}.txt", std::process::id(), slug));
+        std::fs::write(&file, &text)?;
+        let result = self.run_editor(&file);
+        let edited = std::fs::read_to_string(&file);
+        std::fs::remove_file(&file)?;
+        result?;
+        // editors like to add a newline at the end of the file
+        let edited = edited?.trim_end_matches('\n').to_owned();
+        if edited == text {
+            return Ok("No changes.".to_string());
+        }
+        self.wiki_mut().edit(&slug, index, edited).await?;
+        self.redraw_active_pane(index).await?;
+        Ok(format!("Saved {}!", slug))
+    }
+
+    // how many panes are shown at once
+    fn columns(&self) -> usize {
+        if !self.side_by_side {
+            return 1;
+        }
+        // every pane after the first needs a column for its separator
+        let fit = (self.size.0 + 1) / (MIN_PANE_WIDTH + 1);
+        fit.clamp(1, max(self.lineup().panes.len(), 1))
+    }
+
+    fn visible_panes(&self) -> std::ops::Range<usize> {
+        let lineup = self.lineup();
+        lineup.first..min(lineup.first + self.columns(), lineup.panes.len())
+    }
+
+    // what a pane's header says, with the whole lineup on the active one
+    fn header(&self, pane: usize) -> String {
+        let lineup = self.lineup();
+        let WikiPane { wiki, slug, .. } = &lineup.panes[pane];
+        let wiki_obj = self.wikis.get(wiki).unwrap();
+        let store = &wiki_obj.store.to_string();
+        // generated pages are named by their title instead
+        let title = match wiki_obj.cached(slug) {
+            Some(page) if page.is_ghost() => format!("{} [read-only]", page.title()),
+            _ => slug.to_owned(),
+        };
+        if pane != lineup.active {
+            return format!("{}: {} -- {}", store, wiki, title);
+        }
+        let mut panes: Vec<&str> = (0..lineup.panes.len()).map(|_| "-").collect();
+        panes[lineup.active] = "*";
+        let panes: String = panes.join("|");
+        // the lineup's name only matters once there's more than one
+        let name = if self.lineups.len() > 1 {
+            format!("{} ", lineup.name)
+        } else {
+            String::new()
+        };
+        format!(
+            "\\|v|/ {}: {} -- {} {}|{}|",
+            store, wiki, title, name, panes
+        )
+    }
+
+    // puts a pane on screen, wrapping its page again if the width changed
+    fn place_pane(
+        &mut self,
+        pane: usize,
+        column: usize,
+        width: usize,
+        shared: bool,
+    ) -> Result<(), Error> {
+        let wiki_pane = &mut self.lineups[self.lineup].panes[pane];
+        let rewrap = wiki_pane.pane.width() != width;
+        wiki_pane.pane.place(column, (width, self.size.1), shared);
+        if !rewrap {
+            return Ok(());
+        }
+        let page = self
+            .wikis
+            .get(&wiki_pane.wiki)
+            .and_then(|wiki| wiki.cached(&wiki_pane.slug));
+        if let Some(page) = page {
+            wiki_pane.pane.rewrap(page.lines(width))?;
+        }
+        Ok(())
+    }
+
     pub fn display_active_pane(&mut self) -> Result<(), Error> {
-        let mut lineup: Vec<&str> = (0..self.panes.len()).map(|_| "-").collect();
-        let mut pane = &mut self.panes[self.active_pane];
-        let wiki = &self.pane_to_wiki[self.active_pane];
-        let store = &self.wikis.get(wiki).unwrap().store.to_string();
-        let slug = &self.pane_to_slug[self.active_pane];
-        lineup[self.active_pane] = "*";
-        let lineup: String = lineup.join("|");
-        pane.header = format!("\\|v|/ {}: {} -- {} |{}|", store, wiki, slug, lineup);
-        pane.display()
+        let columns = self.columns();
+        self.lineup_mut().scroll_to_active(columns);
+        let first = self.lineup().first;
+        let width = (self.size.0 + 1) / columns - 1;
+        let mut stdout = stdout();
+        if media::inline_images_supported() {
+            // panes only clear their own images, which misses those of panes now off screen
+            write!(stdout, "{}", media::clear_kitty_images())?;
+        }
+        for pane in self.visible_panes() {
+            let column = (pane - first) * (width + 1);
+            // the last pane takes up whatever's left over
+            let pane_width = if pane + 1 == first + columns {
+                self.size.0 - column
+            } else {
+                width
+            };
+            if column > 0 {
+                for row in 0..self.size.1 - 1 {
+                    stdout.queue(cursor::MoveTo(column as u16 - 1, row as u16))?;
+                    write!(stdout, "│")?;
+                }
+            }
+            self.place_pane(pane, column, pane_width, columns > 1)?;
+            let header = self.header(pane);
+            let lineup = self.lineup_mut();
+            let active = pane == lineup.active;
+            let wiki_pane = &mut lineup.panes[pane];
+            wiki_pane.pane.header = header;
+            wiki_pane.pane.active = active;
+            wiki_pane.pane.display()?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // fits the lineup to a new terminal size
+    fn resize(&mut self, size: (usize, usize)) -> Result<(), Error> {
+        self.size = clamp_size(size);
+        stdout().execute(Clear(ClearType::All))?;
+        // panes on screen are wrapped to their new widths as they're placed,
+        // and the rest when they're next shown
+        self.display_active_pane()?;
+        self.ex.display(self.size.1 as u16 - 1)
     }
 
     fn previous_pane(&mut self) -> Result<(), Error> {
-        let previous_pane = self.active_pane;
-        self.active_pane = max(self.active_pane as isize - 1, 0) as usize;
-        if self.active_pane != previous_pane {
+        let lineup = self.lineup_mut();
+        let previous_pane = lineup.active;
+        lineup.active = max(lineup.active as isize - 1, 0) as usize;
+        if lineup.active != previous_pane {
             self.display_active_pane()?;
         }
         Ok(())
     }
 
     fn next_pane(&mut self) -> Result<(), Error> {
-        let previous_pane = self.active_pane;
-        self.active_pane = min(self.active_pane + 1, self.panes.len() - 1);
-        if self.active_pane != previous_pane {
+        let lineup = self.lineup_mut();
+        let previous_pane = lineup.active;
+        lineup.active = min(lineup.active + 1, lineup.panes.len() - 1);
+        if lineup.active != previous_pane {
             self.display_active_pane()?;
         }
         Ok(())
     }
 
     pub async fn handle_input(&mut self) -> Result<(), Error> {
+        // anything reported while loading, such as pages that couldn't be reopened
+        self.ex.display(self.size.1 as u16 - 1)?;
         loop {
             let event = read()?;
             let mut handled = ExEventStatus::None;
             match event {
-                Event::Mouse(event) => match event {
-                    MouseEvent::Down(_button, x, y, modifiers) => {
-                        // adjust y to account for header
-                        let link = self.panes[self.active_pane].find_link(x, y - 1);
-                        if let Some(link) = link {
-                            let link = link.to_lowercase().replace(" ", "-");
-                            if modifiers == KeyModifiers::SHIFT {
-                                self.run_command(&format!("open end {}", link)).await?;
-                            } else {
-                                self.run_command(&format!("open {}", link)).await?;
-                            }
+                Event::Mouse(MouseEvent::Down(_button, x, y, modifiers)) => {
+                    // clicking on another pane makes it the active one
+                    let clicked = self
+                        .visible_panes()
+                        .find(|pane| self.lineup().panes[*pane].pane.contains(x));
+                    if let Some(pane) = clicked {
+                        if pane != self.lineup().active {
+                            self.lineup_mut().active = pane;
+                            self.display_active_pane()?;
                         }
                     }
-                    _ => {}
-                },
+                    // adjust y to account for header
+                    let link = y
+                        .checked_sub(1)
+                        .and_then(|y| self.active_mut().pane.find_link(x, y));
+                    if let Some(link) = link {
+                        self.follow_link(link, modifiers == KeyModifiers::SHIFT)
+                            .await?;
+                    }
+                }
+                Event::Resize(cols, rows) => self.resize((cols as usize, rows as usize))?,
                 Event::Key(event) => {
                     if self.ex.active() {
                         handled = self.ex.handle_key_press(event);
                     }
                     if handled != ExEventStatus::None {
-                        if let ExEventStatus::Run(command) = handled {
-                            self.run_command(&command).await?;
-                        } else {
-                            self.ex.display(self.size.1 as u16 - 1)?;
+                        match handled {
+                            ExEventStatus::Run(command) => self.run_command(&command).await?,
+                            ExEventStatus::Complete(buffer) => {
+                                self.complete(&buffer).await;
+                                self.ex.display(self.size.1 as u16 - 1)?;
+                            }
+                            _ => self.ex.display(self.size.1 as u16 - 1)?,
                         }
                         continue;
                     }
@@ -415,19 +1537,23 @@ impl Terki {
                         KeyCode::Char('e') => {
                             self.edit_mode = !self.edit_mode;
                             if self.edit_mode {
-                                let active_pane = &mut self.panes[self.active_pane];
-                                active_pane.highlight_index = Some(active_pane.scroll_index);
-                                active_pane.highlight_line()?;
+                                let active_pane = &mut self.active_mut().pane;
+                                // an empty page has nothing to highlight until something's added
+                                if !active_pane.is_empty() {
+                                    active_pane.highlight_index = Some(active_pane.scroll_index);
+                                    active_pane.highlight_line()?;
+                                }
                                 active_pane.display()?;
                             } else {
-                                let active_pane = &mut self.panes[self.active_pane];
+                                let active_pane = &mut self.active_mut().pane;
                                 active_pane.reset_line(active_pane.highlight_index);
                                 active_pane.highlight_index = None;
+                                active_pane.display()?;
                             }
                         }
                         KeyCode::Up | KeyCode::Char('k') => {
-                            let active_pane = &mut self.panes[self.active_pane];
-                            let scroll_by = if let Some(_) = active_pane.highlight_prev()? {
+                            let active_pane = &mut self.active_mut().pane;
+                            let scroll_by = if active_pane.highlight_prev()?.is_some() {
                                 active_pane.display()?;
                                 active_pane.compute_scroll_up(active_pane.highlight_index.unwrap())
                             } else {
@@ -438,8 +1564,8 @@ impl Terki {
                             }
                         }
                         KeyCode::Down | KeyCode::Char('j') => {
-                            let active_pane = &mut self.panes[self.active_pane];
-                            let scroll_by = if let Some(_) = active_pane.highlight_next()? {
+                            let active_pane = &mut self.active_mut().pane;
+                            let scroll_by = if active_pane.highlight_next()?.is_some() {
                                 active_pane.display()?;
                                 active_pane
                                     .compute_scroll_down(active_pane.highlight_index.unwrap())
@@ -457,10 +1583,26 @@ impl Terki {
                                 .activate_with_prompt(self.size.1 as u16 - 1, "open".to_string())?;
                         }
                         KeyCode::Char('r') => self.run_command("reload").await?,
+                        KeyCode::Char('i') if self.edit_mode => self.run_command("editor").await?,
                         KeyCode::Char('x') => self.run_command("close").await?,
+                        KeyCode::Backspace if event.modifiers.contains(KeyModifiers::SHIFT) => {
+                            self.run_command("forward").await?
+                        }
+                        KeyCode::Backspace => self.run_command("back").await?,
+                        KeyCode::Char('v') => self.run_command("layout").await?,
+                        KeyCode::Char('<') => self.scroll_columns(-1).await?,
+                        KeyCode::Char('>') => self.scroll_columns(1).await?,
                         KeyCode::Char('n') => {
-                            self.panes[self.active_pane].search_next("[[")?;
-                            self.panes[self.active_pane].display()?;
+                            self.active_mut().pane.next_link()?;
+                            self.active_mut().pane.display()?;
+                        }
+                        KeyCode::Tab => self.active_mut().pane.focus_link(true)?,
+                        KeyCode::BackTab => self.active_mut().pane.focus_link(false)?,
+                        KeyCode::Enter => {
+                            if let Some(link) = self.active_mut().pane.focused_link() {
+                                let at_end = event.modifiers.contains(KeyModifiers::SHIFT);
+                                self.follow_link(link, at_end).await?;
+                            }
                         }
                         KeyCode::Char(':') => {
                             self.ex.handle_key_press(event);
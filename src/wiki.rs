@@ -1,12 +1,92 @@
-use anyhow::{Error, Result};
-use reqwest;
-use serde::Deserialize;
-use serde_json::Value;
-use std::collections::HashMap;
+use crate::neighborhood::fetch_sitemap;
+use crate::{diff, highlight, html, markdown, media, render, InlineImage, Neighbor, SitemapEntry};
+use anyhow::{anyhow, Error, Result};
+use chrono::{Local, TimeZone};
+use crossterm::style::{Attribute, Color, ContentStyle};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use url::Url;
 
+fn client(session: &Option<String>) -> Result<reqwest::Client> {
+    use reqwest::header;
+    let mut headers = header::HeaderMap::new();
+    if let Some(session) = session {
+        let value = format!("wikiTlsSession={}", session);
+        headers.insert(header::COOKIE, header::HeaderValue::from_str(&value)?);
+    }
+    Ok(reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?)
+}
+
+/// The error for a slug that a store has no page for.
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "page not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The slug a page title is stored under.
+///
+/// Whitespace runs become a single dash and anything other than ascii
+/// letters, digits and dashes is dropped, as the web client does.
+pub fn slug(title: &str) -> String {
+    title
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("-")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// A site's name the way the web client writes it, port included.
+pub fn host(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+/// A title guessed from a slug, for pages that don't exist yet.
+pub fn title(slug: &str) -> String {
+    slug.split('-')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// wiki item ids are 16 random hex digits
+fn random_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
 #[derive(Debug)]
 pub enum PageStore {
     Local {
@@ -24,7 +104,11 @@ impl PageStore {
     async fn retrieve(&mut self, slug: &str) -> Result<Page> {
         let page = match self {
             PageStore::Local { path } => {
-                serde_json::from_str(&fs::read_to_string(path.join("pages").join(slug))?)?
+                let file = path.join("pages").join(slug);
+                if !file.exists() {
+                    return Err(NotFound(slug.to_owned()).into());
+                }
+                serde_json::from_str(&fs::read_to_string(file)?)?
             }
             PageStore::Http {
                 url,
@@ -33,18 +117,13 @@ impl PageStore {
                 ..
             } => {
                 if !cache.contains_key(slug) {
-                    use reqwest::header;
-                    let mut headers = header::HeaderMap::new();
-                    if let Some(session) = session {
-                        let value = format!("wikiTlsSession={}", session);
-                        headers.insert(header::COOKIE, header::HeaderValue::from_str(&value)?);
-                    }
                     let url = Url::parse(url)?;
                     let page_url = url.join(&format!("{}.json", slug))?;
-                    let client = reqwest::Client::builder()
-                        .default_headers(headers)
-                        .build()?;
-                    let body = client.get(page_url).send().await?.text().await?;
+                    let response = client(session)?.get(page_url).send().await?;
+                    if response.status() == reqwest::StatusCode::NOT_FOUND {
+                        return Err(NotFound(slug.to_owned()).into());
+                    }
+                    let body = response.text().await?;
                     cache.insert(slug.to_owned(), body);
                 }
                 serde_json::from_str(cache.get(slug).as_ref().unwrap())?
@@ -53,18 +132,111 @@ impl PageStore {
         Ok(page)
     }
 
-    pub fn to_string(&self) -> String {
+    // every page in the store, as the server's sitemap lists them
+    async fn sitemap(&self) -> Result<Vec<SitemapEntry>> {
+        match self {
+            PageStore::Local { path } => {
+                let mut entries = Vec::new();
+                let pages = path.join("pages");
+                if !pages.exists() {
+                    return Ok(entries);
+                }
+                for file in fs::read_dir(pages)? {
+                    let file = file?;
+                    let slug = match file.file_name().to_str() {
+                        Some(slug) if !slug.starts_with('.') => slug.to_owned(),
+                        _ => continue,
+                    };
+                    // skip anything that isn't a page rather than failing the lot
+                    let page: Page = match serde_json::from_str(&fs::read_to_string(file.path())?) {
+                        Ok(page) => page,
+                        Err(_) => continue,
+                    };
+                    let modified = file
+                        .metadata()?
+                        .modified()?
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_millis() as u64)
+                        .ok();
+                    entries.push(SitemapEntry {
+                        slug,
+                        title: page.title.clone(),
+                        date: page.date().or(modified),
+                        synopsis: page.synopsis(),
+                    });
+                }
+                Ok(entries)
+            }
+            PageStore::Http { url, .. } => fetch_sitemap(url).await,
+        }
+    }
+
+    async fn store(&mut self, slug: &str, page: &Page, action: &JournalEntry) -> Result<()> {
+        match self {
+            PageStore::Local { path } => {
+                fs::create_dir_all(path.join("pages"))?;
+                fs::write(
+                    path.join("pages").join(slug),
+                    serde_json::to_string_pretty(page)?,
+                )?;
+            }
+            PageStore::Http {
+                url,
+                cache,
+                session,
+                ..
+            } => {
+                let url = Url::parse(url)?;
+                let action_url = url.join(&format!("page/{}/action", slug))?;
+                let mut json = serde_json::to_value(action)?;
+                if let (JournalEntry::Fork { .. }, Value::Object(fields)) = (action, &mut json) {
+                    // the server takes the copy from the action and adds the fork to it itself
+                    let mut copy = serde_json::to_value(page)?;
+                    if let Some(Value::Array(journal)) = copy.get_mut("journal") {
+                        journal.pop();
+                    }
+                    fields.insert("forkPage".to_string(), copy);
+                }
+                let response = client(session)?
+                    .put(action_url)
+                    .form(&[("action", json.to_string())])
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "Unable to save {}: {}",
+                        slug,
+                        response.status().as_str()
+                    ));
+                }
+                cache.insert(slug.to_owned(), serde_json::to_string(page)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The site name other wikis know this one by; local wikis aren't served anywhere.
+    pub fn host(&self) -> Option<String> {
+        match self {
+            PageStore::Http { url, .. } => Url::parse(url).ok().as_ref().and_then(host),
+            PageStore::Local { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PageStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
-            PageStore::Http { .. } => "remote",
-            PageStore::Local { .. } => "local",
+            PageStore::Http { .. } => write!(f, "remote"),
+            PageStore::Local { .. } => write!(f, "local"),
         }
-        .to_string()
     }
 }
 #[derive(Debug)]
 pub struct Wiki {
     pub store: PageStore,
     pages: HashMap<String, Page>,
+    sitemap: Option<Vec<SitemapEntry>>,
 }
 
 impl Wiki {
@@ -72,20 +244,177 @@ impl Wiki {
         Wiki {
             store,
             pages: HashMap::new(),
+            sitemap: None,
         }
     }
 
-    pub async fn page<'a>(&'a mut self, slug: &str) -> Result<&'a mut Page, Error> {
+    /// Registers a generated page so it can be opened like any other.
+    ///
+    /// Generated pages are named with an underscore, which `slug` never
+    /// produces, so that they can't take the place of a real page.
+    pub fn add_ghost(&mut self, slug: &str, page: Page) {
+        self.pages.insert(slug.to_owned(), page);
+    }
+
+    pub fn is_ghost(&self, slug: &str) -> bool {
+        self.pages.get(slug).map(|page| page.ghost).unwrap_or(false)
+    }
+
+    /// A page that has already been retrieved, if any.
+    pub fn cached(&self, slug: &str) -> Option<&Page> {
+        self.pages.get(slug)
+    }
+
+    pub async fn page(&mut self, slug: &str) -> Result<&mut Page, Error> {
         if !self.pages.contains_key(slug) {
-            let retrieved = self.store.retrieve(&slug).await?;
+            let retrieved = self.store.retrieve(slug).await?;
             self.pages.insert(slug.to_owned(), retrieved);
         }
         Ok(self.pages.get_mut(slug).unwrap())
     }
 
+    /// Every page on the wiki, fetched the first time it's asked for.
+    pub async fn sitemap(&mut self) -> Result<&[SitemapEntry], Error> {
+        if self.sitemap.is_none() {
+            self.sitemap = Some(self.store.sitemap().await?);
+        }
+        Ok(self.sitemap.as_ref().unwrap())
+    }
+
+    /// Every page on the wiki, fetched afresh to pick up changes made elsewhere.
+    pub async fn refresh_sitemap(&mut self) -> Result<&[SitemapEntry], Error> {
+        self.sitemap = None;
+        self.sitemap().await
+    }
+
+    // applies the action to a copy of the cached page, which replaces it once it's persisted
+    async fn perform(&mut self, slug: &str, action: JournalEntry) -> Result<(), Error> {
+        let mut page = self.page(slug).await?.clone();
+        if page.ghost {
+            return Err(anyhow!("{} is read-only!", slug));
+        }
+        page.apply(&action)?;
+        self.store.store(slug, &page, &action).await?;
+        self.pages.insert(slug.to_owned(), page);
+        // titles, synopses and dates may all have changed
+        self.sitemap = None;
+        Ok(())
+    }
+
+    pub async fn edit(&mut self, slug: &str, index: usize, text: String) -> Result<(), Error> {
+        let mut item = self.page(slug).await?.item(index)?.clone();
+        item.text = Some(text);
+        let action = JournalEntry::Edit {
+            id: item.id.clone(),
+            item,
+            date: now(),
+        };
+        self.perform(slug, action).await
+    }
+
+    /// Adds a paragraph after the item at `after`, or at the top of the page when `None`.
+    pub async fn add(
+        &mut self,
+        slug: &str,
+        after: Option<usize>,
+        text: String,
+    ) -> Result<(), Error> {
+        let page = self.page(slug).await?;
+        let after = match after {
+            Some(index) => Some(page.item(index)?.id.clone()),
+            None => None,
+        };
+        let item = Item::paragraph(text);
+        let action = JournalEntry::Add {
+            id: item.id.clone(),
+            after,
+            date: now(),
+            item,
+        };
+        self.perform(slug, action).await
+    }
+
+    pub async fn remove(&mut self, slug: &str, index: usize) -> Result<(), Error> {
+        let id = self.page(slug).await?.item(index)?.id.clone();
+        let action = JournalEntry::Remove { id, date: now() };
+        self.perform(slug, action).await
+    }
+
+    pub async fn move_item(&mut self, slug: &str, from: usize, to: usize) -> Result<(), Error> {
+        let page = self.page(slug).await?;
+        let id = page.item(from)?.id.clone();
+        page.item(to)?;
+        let mut order: Vec<String> = page.story.iter().map(|item| item.id.clone()).collect();
+        let moved = order.remove(from);
+        order.insert(to, moved);
+        let action = JournalEntry::Move {
+            id,
+            order,
+            date: Some(now()),
+        };
+        self.perform(slug, action).await
+    }
+
+    /// Creates an empty page, or one starting with the story of `template`,
+    /// unless there's a page at `slug` already.
+    pub async fn create(
+        &mut self,
+        slug: &str,
+        title: &str,
+        template: Option<&str>,
+    ) -> Result<(), Error> {
+        // never write over a page that's already there
+        if self.pages.contains_key(slug) || self.store.retrieve(slug).await.is_ok() {
+            return Err(anyhow!("{} already exists!", slug));
+        }
+        let story = match template {
+            Some(template) => self.page(template).await?.story.clone(),
+            None => Vec::new(),
+        };
+        let action = JournalEntry::Create {
+            item: NewPage {
+                title: title.to_owned(),
+                story,
+            },
+            date: now(),
+        };
+        let mut page = Page::new(title.to_owned(), Vec::new());
+        page.apply(&action)?;
+        self.store.store(slug, &page, &action).await?;
+        self.pages.insert(slug.to_owned(), page);
+        self.sitemap = None;
+        Ok(())
+    }
+
+    /// Saves a copy of a page from another site, recording where it came from.
+    pub async fn fork(
+        &mut self,
+        slug: &str,
+        mut page: Page,
+        site: Option<String>,
+    ) -> Result<(), Error> {
+        if page.ghost {
+            return Err(anyhow!("{} is read-only!", slug));
+        }
+        let action = JournalEntry::Fork {
+            site,
+            date: Some(now()),
+        };
+        page.apply(&action)?;
+        self.store.store(slug, &page, &action).await?;
+        self.pages.insert(slug.to_owned(), page);
+        self.sitemap = None;
+        Ok(())
+    }
+
     pub async fn login(&mut self) -> Result<(), Error> {
-        match &self.store {
-            PageStore::Http { url, password, .. } => {
+        match &mut self.store {
+            PageStore::Http {
+                url,
+                password,
+                session,
+                ..
+            } => {
                 let password = password
                     .as_ref()
                     .ok_or(anyhow::anyhow!("No password set!"))?;
@@ -101,6 +430,13 @@ impl Wiki {
                         response.status().as_str()
                     ));
                 }
+                // keep the session cookie so later actions are authorized
+                for cookie in response.headers().get_all(reqwest::header::SET_COOKIE) {
+                    let cookie = cookie.to_str()?.split(';').next().unwrap_or("");
+                    if let Some(value) = cookie.strip_prefix("wikiTlsSession=") {
+                        *session = Some(value.to_owned());
+                    }
+                }
             }
             PageStore::Local { .. } => {
                 return Err(anyhow::anyhow!("Login not needed for a local site!"));
@@ -111,30 +447,173 @@ impl Wiki {
 
     pub fn password(&mut self, new_password: String) -> Result<(), Error> {
         if let PageStore::Http { password, .. } = &mut self.store {
-            std::mem::replace(password, Some(new_password));
+            *password = Some(new_password);
             return Ok(());
         }
         Err(anyhow::anyhow!("Not a remote site!"))
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Item {
     id: String,
     r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     text: Option<String>,
+    // where a reference points
+    #[serde(skip_serializing_if = "Option::is_none")]
+    site: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    // where an image, video or audio item's media is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<String>,
+    // a data item's table, its rows keyed by column or in column order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    columns: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Vec<Value>>,
+    // plugin specific fields, kept so edits don't drop them
+    #[serde(flatten)]
+    other: Map<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(tag = "type")]
+impl Item {
+    fn paragraph(text: String) -> Item {
+        Item {
+            id: random_id(),
+            r#type: "paragraph".to_string(),
+            text: Some(text),
+            site: None,
+            slug: None,
+            title: None,
+            url: None,
+            caption: None,
+            columns: None,
+            data: None,
+            other: Map::new(),
+        }
+    }
+
+    // videos and audio name their media in their text when they have no url
+    fn media_url(&self) -> Option<String> {
+        if let Some(url) = &self.url {
+            return Some(url.clone());
+        }
+        let text = self.text.as_deref()?;
+        match self.r#type.as_str() {
+            "video" => media::video_url(text),
+            "audio" => text
+                .lines()
+                .find(|line| line.starts_with("http"))
+                .map(|line| line.trim().to_owned()),
+            _ => None,
+        }
+    }
+
+    // the words shown under the media, leaving out the lines naming it
+    fn media_caption(&self) -> String {
+        if let Some(caption) = &self.caption {
+            return caption.clone();
+        }
+        let text = self.text.as_deref().unwrap_or("");
+        match self.r#type.as_str() {
+            "video" if self.url.is_none() => text.lines().skip(1).collect::<Vec<&str>>().join("\n"),
+            "audio" => text
+                .lines()
+                .filter(|line| !line.starts_with("http"))
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            _ => text.to_owned(),
+        }
+    }
+
+    // a data item's column names and the text of each cell
+    fn table(&self) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+        let data = self.data.as_ref()?;
+        let columns = match &self.columns {
+            Some(columns) => columns.clone(),
+            // without columns, go by the first row's keys
+            None => match data.first() {
+                Some(Value::Object(row)) => row.keys().cloned().collect(),
+                _ => Vec::new(),
+            },
+        };
+        let cell = |value: Option<&Value>| match value {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        };
+        let rows = data
+            .iter()
+            .map(|row| match row {
+                Value::Object(row) => columns.iter().map(|column| cell(row.get(column))).collect(),
+                Value::Array(row) => row.iter().map(|value| cell(Some(value))).collect(),
+                value => vec![cell(Some(value))],
+            })
+            .collect();
+        Some((columns, rows))
+    }
+
+    fn pagefold(text: String) -> Item {
+        Item {
+            r#type: "pagefold".to_string(),
+            ..Item::paragraph(text)
+        }
+    }
+
+    fn reference(site: &str, entry: &SitemapEntry) -> Item {
+        Item {
+            id: random_id(),
+            r#type: "reference".to_string(),
+            text: entry.synopsis.clone(),
+            site: Some(site.to_owned()),
+            slug: Some(entry.slug.clone()),
+            title: Some(entry.title.clone()),
+            url: None,
+            caption: None,
+            columns: None,
+            data: None,
+            other: Map::new(),
+        }
+    }
+
+    // a reference to a neighbor's welcome page, with the status of its sitemap
+    fn neighbor(neighbor: &Neighbor) -> Item {
+        Item {
+            r#type: "reference".to_string(),
+            site: Some(neighbor.site.clone()),
+            slug: Some("welcome-visitors".to_string()),
+            title: Some("Welcome Visitors".to_string()),
+            ..Item::paragraph(neighbor.status.clone())
+        }
+    }
+}
+
+// the page a create action starts from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct NewPage {
+    #[serde(default)]
+    title: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    story: Vec<Item>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
 enum JournalEntry {
     Create {
-        item: Item,
+        item: NewPage,
         date: u64,
     },
     Add {
         id: String,
-        after: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        after: Option<String>,
         date: u64,
         item: Item,
     },
@@ -147,35 +626,1200 @@ enum JournalEntry {
         id: String,
         date: u64,
     },
+    // older clients didn't date moves or forks
     Move {
         id: String,
         order: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        date: Option<u64>,
     },
     Fork {
-        data: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        site: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        date: Option<u64>,
+    },
+    // an entry terki can't read, kept so the journal still lines up with its revisions
+    #[serde(skip)]
+    Unknown {
+        action: String,
+        date: Option<u64>,
     },
 }
 
+impl JournalEntry {
+    fn date(&self) -> Option<u64> {
+        match self {
+            JournalEntry::Create { date, .. }
+            | JournalEntry::Add { date, .. }
+            | JournalEntry::Edit { date, .. }
+            | JournalEntry::Remove { date, .. } => Some(*date),
+            JournalEntry::Move { date, .. }
+            | JournalEntry::Fork { date, .. }
+            | JournalEntry::Unknown { date, .. } => *date,
+        }
+    }
+}
+
+/// Where a link on a page leads.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkTarget {
+    /// A page on the same wiki.
+    Slug(String),
+    /// A page on another site, as named by a reference.
+    Site {
+        site: String,
+        slug: String,
+    },
+    Url(String),
+}
+
+// the index of a link in the links table, adding it if needed
+fn link_index(links: &mut Vec<(String, LinkTarget)>, text: &str, target: LinkTarget) -> usize {
+    match links.iter().position(|(t, s)| t == text && *s == target) {
+        Some(index) => index,
+        None => {
+            links.push((text.to_owned(), target));
+            links.len() - 1
+        }
+    }
+}
+
+fn link_style() -> ContentStyle {
+    ContentStyle::new().foreground(Color::Cyan)
+}
+
+fn external_link_style() -> ContentStyle {
+    ContentStyle::new()
+        .foreground(Color::Blue)
+        .attribute(Attribute::Underlined)
+}
+
+// wraps a line of wiki text, keeping track of the links in it
+fn render_text(
+    text: &str,
+    cols: usize,
+    prefix: &str,
+    line_index: usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let (text, found) = render::links(text);
+    let ranges = render::wrap(&text, cols.saturating_sub(prefix.chars().count()));
+    let mut lines: Vec<DisplayLine> = ranges
+        .iter()
+        .map(|range| DisplayLine {
+            text: format!("{}{}", prefix, &text[range.clone()]),
+            line_index: Some(line_index),
+            spans: Vec::new(),
+        })
+        .collect();
+    for (span, found) in found {
+        let (link, style) = match found {
+            render::Link::Internal(text) => (
+                link_index(links, text, LinkTarget::Slug(slug(text))),
+                link_style(),
+            ),
+            render::Link::External { url, label } => (
+                link_index(links, label, LinkTarget::Url(url.to_owned())),
+                external_link_style(),
+            ),
+        };
+        for (i, range) in render::split_span(&span, &ranges) {
+            lines[i].spans.push(Span {
+                start: prefix.len() + range.start,
+                end: prefix.len() + range.end,
+                style: style.clone(),
+                link: Some(link),
+            });
+        }
+    }
+    lines
+}
+
+// wraps markdown inline text behind a prefix, which the first line can have its own of
+#[allow(clippy::too_many_arguments)]
+fn render_inline(
+    text: &str,
+    cols: usize,
+    prefixes: (&str, &str),
+    prefix_style: Option<ContentStyle>,
+    attributes: &[Attribute],
+    line_index: usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let (text, runs) = markdown::inline(text);
+    let (first, rest) = prefixes;
+    let width = cols.saturating_sub(first.chars().count().max(rest.chars().count()));
+    let ranges = render::wrap(&text, width);
+    let prefix = |i: usize| if i == 0 { first } else { rest };
+    let mut lines: Vec<DisplayLine> = ranges
+        .iter()
+        .enumerate()
+        .map(|(i, range)| DisplayLine {
+            text: format!("{}{}", prefix(i), &text[range.clone()]),
+            line_index: Some(line_index),
+            spans: match &prefix_style {
+                Some(style) if !prefix(i).is_empty() => vec![Span {
+                    start: 0,
+                    end: prefix(i).len(),
+                    style: style.clone(),
+                    link: None,
+                }],
+                _ => Vec::new(),
+            },
+        })
+        .collect();
+    for run in runs {
+        let (mut style, link) = match &run.link {
+            Some(render::Link::Internal(title)) => (
+                link_style(),
+                Some(link_index(links, title, LinkTarget::Slug(slug(title)))),
+            ),
+            Some(render::Link::External { url, label }) => (
+                external_link_style(),
+                Some(link_index(links, label, LinkTarget::Url((*url).to_owned()))),
+            ),
+            None if run.code => (ContentStyle::new().foreground(Color::Yellow), None),
+            None => (ContentStyle::new(), None),
+        };
+        let mut plain = link.is_none() && !run.code;
+        for attribute in attributes {
+            style = style.attribute(*attribute);
+            plain = false;
+        }
+        if run.bold {
+            style = style.attribute(Attribute::Bold);
+            plain = false;
+        }
+        if run.italic {
+            style = style.attribute(Attribute::Italic);
+            plain = false;
+        }
+        if plain {
+            continue;
+        }
+        for (i, range) in render::split_span(&run.range, &ranges) {
+            lines[i].spans.push(Span {
+                start: prefix(i).len() + range.start,
+                end: prefix(i).len() + range.end,
+                style: style.clone(),
+                link,
+            });
+        }
+    }
+    lines
+}
+
+// cells are wrapped within their columns, which shrink from the widest until they fit
+fn render_table(
+    header: bool,
+    rows: &[Vec<String>],
+    cols: usize,
+    line_index: usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![1; columns];
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            let width = markdown::inline(cell).0.chars().count();
+            widths[column] = widths[column].max(width);
+        }
+    }
+    let available = cols.saturating_sub(3 * columns.saturating_sub(1));
+    while widths.iter().sum::<usize>() > available {
+        let widest = (0..columns).max_by_key(|column| widths[*column]).unwrap();
+        if widths[widest] == 1 {
+            break;
+        }
+        widths[widest] -= 1;
+    }
+    let separator = " │ ";
+    let grey = ContentStyle::new().foreground(Color::DarkGrey);
+    let mut lines = Vec::new();
+    for (r, row) in rows.iter().enumerate() {
+        let attributes: &[Attribute] = if header && r == 0 {
+            &[Attribute::Bold]
+        } else {
+            &[]
+        };
+        let cells: Vec<Vec<DisplayLine>> = (0..columns)
+            .map(|column| {
+                let cell = row.get(column).map(String::as_str).unwrap_or("");
+                let width = widths[column];
+                render_inline(cell, width, ("", ""), None, attributes, line_index, links)
+            })
+            .collect();
+        let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+        for i in 0..height {
+            let mut line = DisplayLine {
+                text: String::new(),
+                line_index: Some(line_index),
+                spans: Vec::new(),
+            };
+            for (column, cell) in cells.iter().enumerate() {
+                if column > 0 {
+                    line.spans.push(Span {
+                        start: line.text.len(),
+                        end: line.text.len() + separator.len(),
+                        style: grey.clone(),
+                        link: None,
+                    });
+                    line.text.push_str(separator);
+                }
+                let offset = line.text.len();
+                if let Some(piece) = cell.get(i) {
+                    line.text.push_str(&piece.text);
+                    line.spans.extend(piece.spans.iter().map(|span| Span {
+                        start: offset + span.start,
+                        end: offset + span.end,
+                        ..span.clone()
+                    }));
+                }
+                let shown = line.text[offset..].chars().count();
+                if column + 1 < columns {
+                    line.text
+                        .push_str(&" ".repeat(widths[column].saturating_sub(shown)));
+                }
+            }
+            lines.push(line);
+        }
+        if header && r == 0 {
+            let rule = widths
+                .iter()
+                .map(|width| "─".repeat(*width))
+                .collect::<Vec<String>>()
+                .join("─┼─");
+            lines.push(DisplayLine {
+                spans: vec![Span {
+                    start: 0,
+                    end: rule.len(),
+                    style: grey.clone(),
+                    link: None,
+                }],
+                text: rule,
+                line_index: Some(line_index),
+            });
+        }
+    }
+    lines
+}
+
+// markdown blocks, each highlighted as a line of their own
+fn render_markdown(
+    text: &str,
+    cols: usize,
+    line_index: &mut usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let mut lines = Vec::new();
+    let grey = ContentStyle::new().foreground(Color::DarkGrey);
+    for block in markdown::blocks(text) {
+        let mut block_lines = match block {
+            markdown::Block::Heading(level, text) => {
+                let attributes: &[Attribute] = match level {
+                    1 => &[Attribute::Bold, Attribute::Underlined],
+                    2 => &[Attribute::Bold],
+                    _ => &[Attribute::Underlined],
+                };
+                render_inline(&text, cols, ("", ""), None, attributes, *line_index, links)
+            }
+            markdown::Block::Paragraph(text) => {
+                render_inline(&text, cols, ("", ""), None, &[], *line_index, links)
+            }
+            markdown::Block::ListItem(depth, marker, text) => {
+                let first = format!("{}{} ", "  ".repeat(depth), marker);
+                let rest = " ".repeat(first.chars().count());
+                let prefixes = (first.as_str(), rest.as_str());
+                render_inline(&text, cols, prefixes, None, &[], *line_index, links)
+            }
+            markdown::Block::Quote(text) => {
+                let prefixes = ("│ ", "│ ");
+                let attributes = &[Attribute::Italic];
+                render_inline(
+                    &text,
+                    cols,
+                    prefixes,
+                    Some(grey.clone()),
+                    attributes,
+                    *line_index,
+                    links,
+                )
+            }
+            markdown::Block::Code(code) => {
+                let width = cols.saturating_sub(2).max(1);
+                let mut code_lines = Vec::new();
+                for line in code {
+                    // code keeps its spacing, so long lines break mid-word
+                    let chars: Vec<char> = line.chars().collect();
+                    let chunks: Vec<String> = match chars.len() {
+                        0 => vec![String::new()],
+                        _ => chars.chunks(width).map(|c| c.iter().collect()).collect(),
+                    };
+                    for chunk in chunks {
+                        code_lines.push(DisplayLine {
+                            text: format!("  {}", chunk),
+                            line_index: Some(*line_index),
+                            spans: vec![Span {
+                                start: 2,
+                                end: 2 + chunk.len(),
+                                style: ContentStyle::new().foreground(Color::Yellow),
+                                link: None,
+                            }],
+                        });
+                    }
+                }
+                code_lines
+            }
+            markdown::Block::Table(header, rows) => {
+                render_table(header, &rows, cols, *line_index, links)
+            }
+            markdown::Block::Rule => {
+                let rule = "─".repeat(cols);
+                vec![DisplayLine {
+                    spans: vec![Span {
+                        start: 0,
+                        end: rule.len(),
+                        style: grey.clone(),
+                        link: None,
+                    }],
+                    text: rule,
+                    line_index: Some(*line_index),
+                }]
+            }
+        };
+        lines.append(&mut block_lines);
+        *line_index += 1;
+    }
+    // keep empty items selectable
+    if lines.is_empty() {
+        lines.push(DisplayLine {
+            text: String::new(),
+            line_index: Some(*line_index),
+            spans: Vec::new(),
+        });
+        *line_index += 1;
+    }
+    lines
+}
+
+// code as written, in a box with the language on top:
+// ┌─ rust ───┐
+// │ fn x() { │
+// └──────────┘
+// lines too long for the box are cut short rather than wrapped
+fn render_code(item: &Item, cols: usize, line_index: usize) -> Vec<DisplayLine> {
+    let code = item.text.as_deref().unwrap_or("").replace('\t', "    ");
+    let hint = ["language", "lang"]
+        .iter()
+        .find_map(|field| item.other.get(*field).and_then(Value::as_str));
+    let syntax = highlight::syntax(hint, &code);
+    let width = cols.saturating_sub(4).max(1);
+    let grey = ContentStyle::new().foreground(Color::DarkGrey);
+    let border = |text: String| DisplayLine {
+        spans: vec![Span {
+            start: 0,
+            end: text.len(),
+            style: grey.clone(),
+            link: None,
+        }],
+        text,
+        line_index: Some(line_index),
+    };
+    let label = syntax.map_or(String::new(), |syntax| format!(" {} ", syntax.name));
+    let top = format!(
+        "┌─{}{}┐",
+        label,
+        "─".repeat((width + 1).saturating_sub(label.chars().count()))
+    );
+    let mut lines = vec![border(top)];
+    let mut in_comment = false;
+    for line in code.lines() {
+        let pieces = match syntax {
+            Some(syntax) => highlight::highlight(line, syntax, &mut in_comment),
+            None => Vec::new(),
+        };
+        let truncated = line.chars().count() > width;
+        let keep = if truncated { width - 1 } else { width };
+        let mut shown: String = line.chars().take(keep).collect();
+        // highlighting stops where the line is cut
+        let cut = shown.len();
+        if truncated {
+            shown.push('…');
+        }
+        let padding = " ".repeat(width - shown.chars().count());
+        let text = format!("│ {}{} │", shown, padding);
+        let mut spans = vec![Span {
+            start: 0,
+            end: "│".len(),
+            style: grey.clone(),
+            link: None,
+        }];
+        let offset = "│ ".len();
+        for (range, color) in pieces {
+            let end = range.end.min(cut);
+            if range.start < end {
+                spans.push(Span {
+                    start: offset + range.start,
+                    end: offset + end,
+                    style: ContentStyle::new().foreground(color),
+                    link: None,
+                });
+            }
+        }
+        spans.push(Span {
+            start: text.len() - "│".len(),
+            end: text.len(),
+            style: grey.clone(),
+            link: None,
+        });
+        lines.push(DisplayLine {
+            text,
+            line_index: Some(line_index),
+            spans,
+        });
+    }
+    lines.push(border(format!("└{}┘", "─".repeat(width + 2))));
+    lines
+}
+
+// a placeholder for an image, video or audio item:
+// ▣ image  640×480  png  23 KB
+// caption
+// url
+fn render_media(
+    item: &Item,
+    cols: usize,
+    line_index: usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let icon = match item.r#type.as_str() {
+        "image" => "▣",
+        "video" => "▶",
+        _ => "♪",
+    };
+    let url = item.media_url();
+    let mut details = vec![format!("{} {}", icon, item.r#type)];
+    let width = item.other.get("width").and_then(Value::as_u64);
+    let height = item.other.get("height").and_then(Value::as_u64);
+    match url.as_deref().and_then(media::decode_data_url) {
+        Some((mime, data)) => {
+            let size = width
+                .zip(height)
+                .map(|(width, height)| (width as u32, height as u32))
+                .or_else(|| media::dimensions(&data));
+            if let Some((width, height)) = size {
+                details.push(format!("{}×{}", width, height));
+            }
+            details.push(media::extension(&mime).to_owned());
+            details.push(format!("{} KB", data.len().div_ceil(1024)));
+        }
+        None => {
+            if let (Some(width), Some(height)) = (width, height) {
+                details.push(format!("{}×{}", width, height));
+            }
+        }
+    }
+    let heading = details.join("  ");
+    let mut lines = vec![DisplayLine {
+        spans: vec![Span {
+            start: 0,
+            end: heading.len(),
+            style: ContentStyle::new().foreground(Color::DarkGrey),
+            link: None,
+        }],
+        text: heading,
+        line_index: Some(line_index),
+    }];
+    for line in item.media_caption().split('\n') {
+        if !line.trim().is_empty() {
+            lines.append(&mut render_text(line, cols, "", line_index, links));
+        }
+    }
+    // embedded media has nothing worth showing as a link
+    if let Some(url) = url.filter(|url| !url.starts_with("data:")) {
+        let label: String = url.chars().take(cols).collect();
+        let link = link_index(links, &label, LinkTarget::Url(url.clone()));
+        lines.push(DisplayLine {
+            spans: vec![Span {
+                start: 0,
+                end: label.len(),
+                style: external_link_style(),
+                link: Some(link),
+            }],
+            text: label,
+            line_index: Some(line_index),
+        });
+    }
+    lines
+}
+
+// the png to draw over an image item, with the cells it needs
+fn inline_image(item: &Item, cols: usize) -> Option<(usize, usize, Vec<u8>)> {
+    if item.r#type != "image" || !media::inline_images_supported() {
+        return None;
+    }
+    let (mime, png) = media::decode_data_url(item.url.as_deref()?)?;
+    if mime != "image/png" {
+        return None;
+    }
+    let (width, height) = media::dimensions(&png)?;
+    let (cols, rows) = media::cells(width, height, cols);
+    Some((cols, rows, png))
+}
+
+// a data item as a table, starting from the given column and showing as many as fit:
+// ▦ data  Densities  (3 rows, columns 1-2 of 4 ▶)
+// Material │ Density
+// ─────────┼────────
+// Iron     │    7.87
+fn render_data(item: &Item, cols: usize, first: usize, line_index: usize) -> Vec<DisplayLine> {
+    let (columns, rows) = item.table().unwrap_or_default();
+    let grey = ContentStyle::new().foreground(Color::DarkGrey);
+    let separator = " │ ";
+    let first = first.min(columns.len().saturating_sub(1));
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .chain(std::iter::once(&columns[column]))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+    // the columns that fit, cutting the first short if even it doesn't
+    let mut shown = Vec::new();
+    let mut used = 0;
+    for (column, width) in widths.iter().enumerate().skip(first) {
+        let gap = if shown.is_empty() {
+            0
+        } else {
+            separator.chars().count()
+        };
+        if !shown.is_empty() && used + gap + width > cols {
+            break;
+        }
+        let width = (*width).min(cols.saturating_sub(used + gap)).max(1);
+        shown.push((column, width));
+        used += gap + width;
+    }
+    let mut summary = format!("▦ {}", item.r#type);
+    if let Some(text) = item.text.as_deref().filter(|text| !text.is_empty()) {
+        summary.push_str(&format!("  {}", text));
+    }
+    let last = shown.last().map_or(0, |(column, _)| column + 1);
+    let mut scroll = format!("{} rows", rows.len());
+    if first > 0 || last < columns.len() {
+        let left = if first > 0 { "◀ " } else { "" };
+        let right = if last < columns.len() { " ▶" } else { "" };
+        scroll.push_str(&format!(
+            ", {}columns {}-{} of {}{}",
+            left,
+            first + 1,
+            last,
+            columns.len(),
+            right
+        ));
+    }
+    summary.push_str(&format!("  ({})", scroll));
+    let summary: String = summary.chars().take(cols).collect();
+    let mut lines = vec![DisplayLine {
+        spans: vec![Span {
+            start: 0,
+            end: summary.len(),
+            style: grey.clone(),
+            link: None,
+        }],
+        text: summary,
+        line_index: Some(line_index),
+    }];
+    let row_line = |cells: &[String], style: Option<ContentStyle>| {
+        let mut line = DisplayLine {
+            text: String::new(),
+            line_index: Some(line_index),
+            spans: Vec::new(),
+        };
+        for (i, (column, width)) in shown.iter().enumerate() {
+            if i > 0 {
+                line.spans.push(Span {
+                    start: line.text.len(),
+                    end: line.text.len() + separator.len(),
+                    style: grey.clone(),
+                    link: None,
+                });
+                line.text.push_str(separator);
+            }
+            let cell = cells.get(*column).map(String::as_str).unwrap_or("");
+            let mut text: String = cell.chars().take(*width).collect();
+            if cell.chars().count() > *width {
+                text.pop();
+                text.push('…');
+            }
+            let padding = " ".repeat(width - text.chars().count());
+            let start = line.text.len();
+            // numbers line up on the right
+            if style.is_none() && cell.parse::<f64>().is_ok() {
+                line.text.push_str(&padding);
+                line.text.push_str(&text);
+            } else {
+                line.text.push_str(&text);
+                line.text.push_str(&padding);
+            }
+            if let Some(style) = &style {
+                line.spans.push(Span {
+                    start,
+                    end: line.text.len(),
+                    style: style.clone(),
+                    link: None,
+                });
+            }
+        }
+        line
+    };
+    lines.push(row_line(
+        &columns,
+        Some(ContentStyle::new().attribute(Attribute::Bold)),
+    ));
+    let rule = shown
+        .iter()
+        .map(|(_, width)| "─".repeat(*width))
+        .collect::<Vec<String>>()
+        .join("─┼─");
+    lines.push(DisplayLine {
+        spans: vec![Span {
+            start: 0,
+            end: rule.len(),
+            style: grey.clone(),
+            link: None,
+        }],
+        text: rule,
+        line_index: Some(line_index),
+    });
+    for row in &rows {
+        lines.push(row_line(row, None));
+    }
+    lines
+}
+
+/// Quotes a cell for csv when it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// a reference to another site's page, drawn as a card:
+// │ Title
+// │ site
+// │ synopsis
+fn render_reference(
+    item: &Item,
+    cols: usize,
+    line_index: usize,
+    links: &mut Vec<(String, LinkTarget)>,
+) -> Vec<DisplayLine> {
+    let prefix = "│ ";
+    let site = item.site.clone().unwrap_or_default();
+    let slug = match (&item.slug, &item.title) {
+        (Some(slug), _) => slug.clone(),
+        (None, Some(title)) => self::slug(title),
+        (None, None) => String::new(),
+    };
+    let title = item.title.clone().unwrap_or_else(|| slug.clone());
+    let link = link_index(
+        links,
+        &title,
+        LinkTarget::Site {
+            site: site.clone(),
+            slug,
+        },
+    );
+    let width = cols.saturating_sub(prefix.chars().count());
+    let heading = vec![
+        (title, link_style().attribute(Attribute::Bold), Some(link)),
+        (site, ContentStyle::new().foreground(Color::DarkGrey), None),
+    ];
+    let mut lines = Vec::new();
+    for (text, style, link) in heading {
+        for range in render::wrap(&text, width) {
+            lines.push(DisplayLine {
+                text: format!("{}{}", prefix, &text[range.clone()]),
+                line_index: Some(line_index),
+                spans: vec![Span {
+                    start: prefix.len(),
+                    end: prefix.len() + range.len(),
+                    style: style.clone(),
+                    link,
+                }],
+            });
+        }
+    }
+    if let Some(text) = &item.text {
+        for line in text.split('\n') {
+            lines.append(&mut render_text(line, cols, prefix, line_index, links));
+        }
+    }
+    lines
+}
+
+/// A styled piece of a line, possibly following an entry in `Rendering.links`.
+#[derive(Clone)]
+pub struct Span {
+    // byte offsets into the line's text
+    pub start: usize,
+    pub end: usize,
+    pub style: ContentStyle,
+    pub link: Option<usize>,
+}
+
+/// A page's lines as drawn at some width, with what they show.
+pub struct Rendering {
+    pub lines: Vec<DisplayLine>,
+    // the story item each line belongs to
+    pub line_items: Vec<Option<usize>>,
+    // the text and target of each link on the page
+    pub links: Vec<(String, LinkTarget)>,
+    pub images: Vec<InlineImage>,
+}
+
 #[derive(Clone)]
 pub struct DisplayLine {
     pub text: String,
     pub line_index: Option<usize>,
+    pub spans: Vec<Span>,
+}
+
+impl DisplayLine {
+    /// A copy of the line with its spans' styles written into the text.
+    pub fn styled(&self) -> DisplayLine {
+        let mut text = String::new();
+        let mut written = 0;
+        for span in &self.spans {
+            text.push_str(&self.text[written..span.start]);
+            let styled = span.style.clone().apply(&self.text[span.start..span.end]);
+            text.push_str(&styled.to_string());
+            written = span.end;
+        }
+        text.push_str(&self.text[written..]);
+        DisplayLine {
+            text,
+            line_index: self.line_index,
+            spans: Vec::new(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Page {
     title: String,
     story: Vec<Item>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     journal: Option<Value>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
     #[serde(skip)]
-    links: Vec<(String, String)>,
+    // the first column shown of each data item scrolled sideways
+    data_columns: HashMap<String, usize>,
     #[serde(skip)]
-    // the item a line belongs to
-    line_item: Vec<Option<usize>>,
+    // generated pages are read-only and never persisted
+    ghost: bool,
+}
+
+fn format_date(date: u64) -> String {
+    match Local.timestamp_millis_opt(date as i64).single() {
+        Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+        None => "????-??-?? ??:??".to_string(),
+    }
+}
+
+fn full_text(item: &Item) -> String {
+    item.text
+        .clone()
+        .unwrap_or_else(|| format!("<{}>", item.r#type))
+}
+
+// the first line of an item's text, for one line summaries
+fn summary(item: &Item) -> String {
+    match item.text.as_deref().and_then(|text| text.lines().next()) {
+        Some(line) if !line.is_empty() => line.to_owned(),
+        _ => format!("<{}>", item.r#type),
+    }
 }
 
 impl Page {
-    fn render_item(&self, cols: usize, line_index: &mut usize, item: &Item) -> Vec<DisplayLine> {
+    fn new(title: String, story: Vec<Item>) -> Page {
+        Page {
+            title,
+            story,
+            journal: None,
+            other: Map::new(),
+            data_columns: HashMap::new(),
+            ghost: false,
+        }
+    }
+
+    fn ghost(title: String, story: Vec<Item>) -> Page {
+        Page {
+            ghost: true,
+            ..Page::new(title, story)
+        }
+    }
+
+    /// A read-only page with a single paragraph of text.
+    pub fn notice(title: String, text: &str) -> Page {
+        Page::ghost(title, vec![Item::paragraph(text.to_owned())])
+    }
+
+    /// A read-only page of reference items to pages on other sites.
+    pub fn references(title: String, references: &[(String, SitemapEntry)]) -> Page {
+        let story = references
+            .iter()
+            .map(|(site, entry)| Item::reference(site, entry))
+            .collect();
+        Page::ghost(title, story)
+    }
+
+    /// A read-only page of references to each neighbor's welcome page.
+    pub fn neighborhood(title: String, neighbors: &[Neighbor]) -> Page {
+        Page::ghost(title, neighbors.iter().map(Item::neighbor).collect())
+    }
+
+    /// A read-only page of references to a site's pages, each with its date.
+    pub fn sitemap(title: String, site: &str, entries: &[SitemapEntry]) -> Page {
+        let story = entries
+            .iter()
+            .map(|entry| {
+                let date = entry
+                    .date
+                    .map(format_date)
+                    .unwrap_or_else(|| "----------------".to_string());
+                let synopsis = entry.synopsis.as_deref().unwrap_or_default();
+                let entry = SitemapEntry {
+                    synopsis: Some(format!("{}  {}", date, synopsis)),
+                    ..entry.clone()
+                };
+                Item::reference(site, &entry)
+            })
+            .collect();
+        Page::ghost(title, story)
+    }
+
+    /// A read-only page of the given pages, newest first, with a fold for each day.
+    pub fn recent(title: String, pages: &[(String, SitemapEntry)]) -> Page {
+        let mut pages: Vec<&(String, SitemapEntry)> = pages
+            .iter()
+            .filter(|(_, entry)| entry.date.is_some())
+            .collect();
+        pages.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.date));
+        let mut story = Vec::new();
+        let mut current_day = None;
+        for (site, entry) in pages {
+            let day = Local
+                .timestamp_millis_opt(entry.date.unwrap_or_default() as i64)
+                .single()
+                .map(|date| date.format("%A %Y-%m-%d").to_string());
+            if day != current_day {
+                let fold = day.clone().unwrap_or_else(|| "Someday".to_string());
+                story.push(Item::pagefold(fold));
+                current_day = day;
+            }
+            story.push(Item::reference(site, entry));
+        }
+        Page::ghost(title, story)
+    }
+
+    /// The journal entries terki understands, oldest first.
+    fn journal(&self) -> Vec<JournalEntry> {
+        match &self.journal {
+            Some(Value::Array(entries)) => entries
+                .iter()
+                .map(|entry| {
+                    serde_json::from_value(entry.clone()).unwrap_or_else(|_| {
+                        JournalEntry::Unknown {
+                            action: entry["type"].as_str().unwrap_or("").to_owned(),
+                            date: entry["date"].as_u64(),
+                        }
+                    })
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A read-only page listing every journal action with its date and item.
+    pub fn history(&self) -> Page {
+        // removed and moved items are only named by id, so track the text as we go
+        let mut texts: HashMap<String, String> = HashMap::new();
+        let mut story = Vec::new();
+        for entry in self.journal() {
+            let (date, action, text) = match &entry {
+                JournalEntry::Create { item, date } => {
+                    for story_item in &item.story {
+                        texts.insert(story_item.id.clone(), summary(story_item));
+                    }
+                    (Some(*date), "create", item.title.clone())
+                }
+                JournalEntry::Add { item, date, .. } => {
+                    texts.insert(item.id.clone(), summary(item));
+                    (Some(*date), "add", summary(item))
+                }
+                JournalEntry::Edit { item, date, .. } => {
+                    texts.insert(item.id.clone(), summary(item));
+                    (Some(*date), "edit", summary(item))
+                }
+                JournalEntry::Remove { id, date } => (
+                    Some(*date),
+                    "remove",
+                    texts.get(id).cloned().unwrap_or_default(),
+                ),
+                JournalEntry::Move { id, date, .. } => {
+                    (*date, "move", texts.get(id).cloned().unwrap_or_default())
+                }
+                JournalEntry::Fork { site, date } => {
+                    (*date, "fork", site.clone().unwrap_or_default())
+                }
+                JournalEntry::Unknown { action, date } => (
+                    *date,
+                    "?",
+                    format!("unknown action {}", action).trim_end().to_owned(),
+                ),
+            };
+            let date = date
+                .map(format_date)
+                .unwrap_or_else(|| "----------------".to_string());
+            story.push(Item::paragraph(format!(
+                "{}  {:<6}  {}",
+                date, action, text
+            )));
+        }
+        Page::ghost(format!("History of {}", self.title), story)
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn is_ghost(&self) -> bool {
+        self.ghost
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.story.len()
+    }
+
+    /// When the page last changed, going by its journal.
+    pub fn date(&self) -> Option<u64> {
+        self.journal().iter().rev().find_map(JournalEntry::date)
+    }
+
+    /// A short description of the page, chosen the way the wiki server does.
+    pub fn synopsis(&self) -> Option<String> {
+        let paragraph = self
+            .story
+            .iter()
+            .take(2)
+            .find(|item| item.r#type == "paragraph");
+        let text = paragraph
+            .or_else(|| self.story.iter().find(|item| item.text.is_some()))?
+            .text
+            .clone()?;
+        Some(text.chars().take(560).collect())
+    }
+
+    /// Every site this page mentions, from its items and the forks in its journal.
+    pub fn sites(&self) -> Vec<String> {
+        let mut sites: Vec<String> = Vec::new();
+        let items = self.story.iter().filter_map(|item| item.site.clone());
+        let forks = self.journal().into_iter().filter_map(|entry| match entry {
+            JournalEntry::Fork { site, .. } => site,
+            _ => None,
+        });
+        for site in items.chain(forks) {
+            if !site.is_empty() && !sites.contains(&site) {
+                sites.push(site);
+            }
+        }
+        sites
+    }
+
+    fn item(&self, index: usize) -> Result<&Item, Error> {
+        self.story
+            .get(index)
+            .ok_or_else(|| anyhow!("No item at {}!", index))
+    }
+
+    pub fn text(&self, index: usize) -> Option<&str> {
+        self.story.get(index).and_then(|item| item.text.as_deref())
+    }
+
+    /// The first story item of the given type.
+    pub fn find_item(&self, r#type: &str) -> Option<usize> {
+        self.story.iter().position(|item| item.r#type == r#type)
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.story.iter().position(|item| item.id == id)
+    }
+
+    // the nth item with the given id, counting from 1
+    fn copy(&self, id: &str, nth: usize) -> Option<&Item> {
+        self.story.iter().filter(|item| item.id == id).nth(nth - 1)
+    }
+
+    // changes the story the way the web client does, tolerating missing ids
+    fn revise(&mut self, action: &JournalEntry) {
+        match action {
+            JournalEntry::Create { item, .. } => {
+                if !item.title.is_empty() {
+                    self.title = item.title.clone();
+                }
+                self.story = item.story.clone();
+            }
+            JournalEntry::Edit { id, item, .. } => match self.position(id) {
+                Some(index) => self.story[index] = item.clone(),
+                None => self.story.push(item.clone()),
+            },
+            JournalEntry::Add { after, item, .. } => {
+                let index = after
+                    .as_deref()
+                    .and_then(|after| self.position(after))
+                    .map_or(0, |index| index + 1);
+                self.story.insert(index, item.clone());
+            }
+            JournalEntry::Remove { id, .. } => {
+                if let Some(index) = self.position(id) {
+                    self.story.remove(index);
+                }
+            }
+            JournalEntry::Move { id, order, .. } => {
+                if let Some(index) = self.position(id) {
+                    // the item goes after whatever precedes it in the new order
+                    let after = match order.iter().position(|other| other == id) {
+                        Some(moved) if moved > 0 => Some(&order[moved - 1]),
+                        _ => None,
+                    };
+                    let item = self.story.remove(index);
+                    let index = after
+                        .and_then(|after| self.position(after))
+                        .map_or(0, |index| index + 1);
+                    self.story.insert(index, item);
+                }
+            }
+            JournalEntry::Fork { .. } | JournalEntry::Unknown { .. } => {}
+        }
+    }
+
+    fn apply(&mut self, action: &JournalEntry) -> Result<(), Error> {
+        self.revise(action);
+        let journal = self.journal.get_or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(entries) = journal {
+            entries.push(serde_json::to_value(action)?);
+        }
+        Ok(())
+    }
+
+    /// A read-only page listing how this story differs from an `older` one.
+    pub fn diff(&self, older: &Page, title: String) -> Page {
+        let ids: Vec<&str> = self.story.iter().map(|item| item.id.as_str()).collect();
+        let old_ids: Vec<&str> = older.story.iter().map(|item| item.id.as_str()).collect();
+        // items on both pages that kept their relative order weren't moved
+        let shared: Vec<&str> = ids
+            .iter()
+            .filter(|id| old_ids.contains(id))
+            .cloned()
+            .collect();
+        let old_shared: Vec<&str> = old_ids
+            .iter()
+            .filter(|id| ids.contains(id))
+            .cloned()
+            .collect();
+        let in_order: HashSet<&str> = diff::common(&old_shared, &shared)
+            .into_iter()
+            .map(|(_, j)| shared[j])
+            .collect();
+        let (mut added, mut removed, mut changed, mut moved) = (0, 0, 0, 0);
+        let mut story = Vec::new();
+        // copied items share an id, so each copy is matched with the same copy on the other page
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for item in &self.story {
+            let copy = seen.entry(item.id.as_str()).or_insert(0);
+            *copy += 1;
+            let old = match older.copy(&item.id, *copy) {
+                Some(old) => old,
+                None => {
+                    added += 1;
+                    story.push(Item::paragraph(format!("+ added: {}", full_text(item))));
+                    continue;
+                }
+            };
+            let was_moved = !in_order.contains(item.id.as_str());
+            if was_moved {
+                moved += 1;
+            }
+            if old != item {
+                changed += 1;
+                let text = if old.r#type == "paragraph" && item.r#type == "paragraph" {
+                    diff::words(&full_text(old), &full_text(item))
+                } else {
+                    format!("[-{}-] {{+{}+}}", full_text(old), full_text(item))
+                };
+                let label = if was_moved {
+                    "moved and changed"
+                } else {
+                    "changed"
+                };
+                story.push(Item::paragraph(format!("~ {}: {}", label, text)));
+            } else if was_moved {
+                story.push(Item::paragraph(format!("> moved: {}", full_text(item))));
+            }
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for old in &older.story {
+            let copy = seen.entry(old.id.as_str()).or_insert(0);
+            *copy += 1;
+            if self.copy(&old.id, *copy).is_none() {
+                removed += 1;
+                story.push(Item::paragraph(format!("- removed: {}", full_text(old))));
+            }
+        }
+        let counts = format!(
+            "{} added, {} removed, {} changed, {} moved",
+            added, removed, changed, moved
+        );
+        story.insert(0, Item::paragraph(counts));
+        Page::ghost(title, story)
+    }
+
+    /// Replays the first `revision` journal entries into a read-only page.
+    pub fn revision(&self, revision: usize) -> Result<Page, Error> {
+        let journal = self.journal();
+        if revision == 0 || revision > journal.len() {
+            return Err(anyhow!("Revision must be between 1 and {}!", journal.len()));
+        }
+        let mut page = Page::ghost(self.title.clone(), Vec::new());
+        for entry in &journal[..revision] {
+            page.revise(entry);
+        }
+        page.title = format!(
+            "{} (revision {} of {})",
+            page.title,
+            revision,
+            journal.len()
+        );
+        Ok(page)
+    }
+
+    fn render_item(
+        &self,
+        cols: usize,
+        line_index: &mut usize,
+        item: &Item,
+        links: &mut Vec<(String, LinkTarget)>,
+    ) -> Vec<DisplayLine> {
         let mut lines = Vec::new();
         let mut prefix = "";
         if item.r#type == "pagefold" {
@@ -183,8 +1827,37 @@ impl Page {
             lines.push(DisplayLine {
                 text: format!("{:-^1$}", heading, cols),
                 line_index: Some(*line_index),
+                spans: Vec::new(),
             });
-            *line_index = *line_index + 1;
+            *line_index += 1;
+            return lines;
+        }
+        if item.r#type == "markdown" {
+            return render_markdown(item.text.as_deref().unwrap_or(""), cols, line_index, links);
+        }
+        if item.r#type == "html" {
+            let markdown = html::to_markdown(item.text.as_deref().unwrap_or(""));
+            return render_markdown(&markdown, cols, line_index, links);
+        }
+        if media::is_media(&item.r#type) {
+            lines = render_media(item, cols, *line_index, links);
+            *line_index += 1;
+            return lines;
+        }
+        if item.r#type == "data" && item.data.is_some() {
+            let first = self.data_columns.get(&item.id).cloned().unwrap_or(0);
+            lines = render_data(item, cols, first, *line_index);
+            *line_index += 1;
+            return lines;
+        }
+        if item.r#type == "code" {
+            lines = render_code(item, cols, *line_index);
+            *line_index += 1;
+            return lines;
+        }
+        if item.r#type == "reference" {
+            lines = render_reference(item, cols, *line_index, links);
+            *line_index += 1;
             return lines;
         }
         if item.r#type != "paragraph" {
@@ -192,43 +1865,308 @@ impl Page {
             lines.push(DisplayLine {
                 text: item.r#type.to_owned(),
                 line_index: Some(*line_index),
+                spans: Vec::new(),
             });
-            *line_index = *line_index + 1;
+            *line_index += 1;
         }
         let text = item.text.as_deref().unwrap_or("<empty>");
         if item.r#type == "paragraph" {
-            // search for links
-            // for each link
-            // add to links
-            // render shortened external link
-            // render as a link
-        }
-        for line in text.split("\n") {
-            for l in textwrap::wrap_iter(&line, cols - prefix.len()) {
+            for line in text.split('\n') {
+                lines.append(&mut render_text(line, cols, "", *line_index, links));
+                *line_index += 1;
+            }
+            return lines;
+        }
+        for line in text.split('\n') {
+            for l in textwrap::wrap_iter(line, cols.saturating_sub(prefix.len()).max(1)) {
                 lines.push(DisplayLine {
-                    text: format!("{}{}", prefix, l.to_string()),
+                    text: format!("{}{}", prefix, l),
                     line_index: Some(*line_index),
+                    spans: Vec::new(),
                 });
             }
-            *line_index = *line_index + 1;
+            *line_index += 1;
         }
-        return lines;
+        lines
     }
 
-    pub fn lines(&mut self, cols: usize) -> Vec<DisplayLine> {
+    pub fn lines(&self, cols: usize) -> Rendering {
         let mut line_index = 0;
         let mut lines = Vec::new();
+        let mut line_items = Vec::new();
+        let mut links = Vec::new();
+        let mut images = Vec::new();
         for (i, item) in self.story.iter().enumerate() {
-            for line in self.render_item(cols, &mut line_index, item) {
-                self.line_item.push(Some(i));
+            for line in self.render_item(cols, &mut line_index, item, &mut links) {
+                line_items.push(Some(i));
                 lines.push(line);
             }
-            self.line_item.push(None);
+            // blank lines for the terminal to draw the image over
+            if let Some((image_cols, rows, png)) = inline_image(item, cols) {
+                images.push(InlineImage {
+                    line: lines.len(),
+                    rows,
+                    cols: image_cols,
+                    png: Arc::new(png),
+                });
+                for _ in 0..rows {
+                    line_items.push(Some(i));
+                    lines.push(DisplayLine {
+                        text: String::new(),
+                        line_index: Some(line_index - 1),
+                        spans: Vec::new(),
+                    });
+                }
+            }
+            line_items.push(None);
             lines.push(DisplayLine {
                 text: "".to_string(),
                 line_index: None,
+                spans: Vec::new(),
             });
         }
-        lines
+        Rendering {
+            lines,
+            line_items,
+            links,
+            images,
+        }
+    }
+
+    /// Scrolls a data item's table sideways by some columns, returning false
+    /// for items that aren't tables.
+    pub fn scroll_columns(&mut self, index: usize, by: isize) -> bool {
+        let item = match self.story.get(index) {
+            Some(item) if item.r#type == "data" => item,
+            _ => return false,
+        };
+        let columns = match item.table() {
+            Some((columns, _)) => columns.len(),
+            None => return false,
+        };
+        let first = self.data_columns.entry(item.id.clone()).or_insert(0);
+        let scrolled = (*first as isize + by).max(0) as usize;
+        *first = scrolled.min(columns.saturating_sub(1));
+        true
+    }
+
+    /// A data item's table as csv, header first.
+    pub fn csv(&self, index: usize) -> Option<String> {
+        let (columns, rows) = self.story.get(index)?.table()?;
+        let mut csv = String::new();
+        for row in std::iter::once(&columns).chain(&rows) {
+            let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
+        }
+        Some(csv)
+    }
+
+    /// Where the given media item's image, video or audio is.
+    pub fn media_url(&self, index: usize) -> Option<String> {
+        let item = self.story.get(index)?;
+        if !media::is_media(&item.r#type) {
+            return None;
+        }
+        item.media_url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a page whose story is paragraphs with the given ids and texts
+    fn page(items: &[(&str, &str)]) -> Page {
+        let story = items
+            .iter()
+            .map(|(id, text)| Item {
+                id: id.to_string(),
+                ..Item::paragraph(text.to_string())
+            })
+            .collect();
+        Page::new("Test".to_string(), story)
+    }
+
+    fn texts(page: &Page) -> Vec<&str> {
+        page.story
+            .iter()
+            .map(|item| item.text.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn slug_drops_punctuation() {
+        assert_eq!(slug("Ward's Wiki"), "wards-wiki");
+        assert_eq!(slug("Pages/Sub Page"), "pagessub-page");
+        assert_eq!(slug("  Lots   of\tspace "), "lots-of-space");
+    }
+
+    #[test]
+    fn slug_drops_non_ascii() {
+        assert_eq!(slug("Café Society"), "caf-society");
+        assert_eq!(slug("日本"), "");
+    }
+
+    #[test]
+    fn references_wrap_to_the_width() {
+        let item: Item = serde_json::from_value(serde_json::json!({
+            "id": "r",
+            "type": "reference",
+            "site": "fed.wiki.org",
+            "slug": "a-long-page-title",
+            "title": "A Long Page Title",
+            "text": "Its synopsis."
+        }))
+        .unwrap();
+        let mut links = Vec::new();
+        let lines = render_reference(&item, 10, 0, &mut links);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "│ A Long",
+                "│ Page",
+                "│ Title",
+                "│ fed.wiki",
+                "│ .org",
+                "│ Its",
+                "│ synopsis",
+                "│ .",
+            ]
+        );
+        // every piece of the title follows the same link
+        assert!(lines[..3].iter().all(|line| line.spans[0].link == Some(0)));
+        // too narrow for even the prefix
+        render_reference(&item, 1, 0, &mut links);
+    }
+
+    #[test]
+    fn diff_of_empty_pages() {
+        let diff = page(&[]).diff(&page(&[]), "Diff".to_string());
+        assert_eq!(texts(&diff), vec!["0 added, 0 removed, 0 changed, 0 moved"]);
+    }
+
+    #[test]
+    fn diff_finds_added_removed_and_changed_items() {
+        let old = page(&[("a", "one"), ("b", "two"), ("c", "three")]);
+        let new = page(&[("a", "one"), ("c", "three 3"), ("d", "four")]);
+        let diff = new.diff(&old, "Diff".to_string());
+        assert_eq!(
+            texts(&diff),
+            vec![
+                "1 added, 1 removed, 1 changed, 0 moved",
+                "~ changed: three {+3+}",
+                "+ added: four",
+                "- removed: two",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_finds_moved_items() {
+        let old = page(&[("a", "one"), ("b", "two"), ("c", "three")]);
+        let new = page(&[("c", "three"), ("a", "one"), ("b", "2")]);
+        let diff = new.diff(&old, "Diff".to_string());
+        assert_eq!(
+            texts(&diff),
+            vec![
+                "0 added, 0 removed, 1 changed, 1 moved",
+                "> moved: three",
+                "~ changed: [-two-] {+2+}",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_matches_copies_of_an_item() {
+        // copied items keep their id, so a page can hold the same id twice
+        let old = page(&[("a", "one"), ("b", "two")]);
+        let new = page(&[("a", "one"), ("b", "two"), ("a", "one")]);
+        let diff = new.diff(&old, "Diff".to_string());
+        assert_eq!(
+            texts(&diff),
+            vec!["1 added, 0 removed, 0 changed, 0 moved", "+ added: one"]
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn csv_of_a_data_item() {
+        let mut page = page(&[("p", "not data")]);
+        page.story.push(
+            serde_json::from_value(serde_json::json!({
+                "id": "d",
+                "type": "data",
+                "columns": ["Name", "Note"],
+                "data": [
+                    {"Name": "Smith, J", "Note": "said \"hi\""},
+                    {"Name": "Doe", "Note": "line\nbreak"},
+                    {"Name": 7}
+                ]
+            }))
+            .unwrap(),
+        );
+        assert_eq!(page.csv(0), None);
+        assert_eq!(
+            page.csv(1).unwrap(),
+            "Name,Note\r\n\"Smith, J\",\"said \"\"hi\"\"\"\r\nDoe,\"line\nbreak\"\r\n7,\r\n"
+        );
+    }
+
+    #[test]
+    fn revisions_replay_the_journal() {
+        let page: Page = serde_json::from_value(serde_json::json!({
+            "title": "Test",
+            "story": [],
+            "journal": [
+                {"type": "create", "date": 1, "item": {"title": "Test", "story": [
+                    {"id": "a", "type": "paragraph", "text": "one"}
+                ]}},
+                {"type": "add", "id": "b", "after": "a", "date": 2,
+                    "item": {"id": "b", "type": "paragraph", "text": "two"}},
+                {"type": "someday", "date": 3},
+                {"type": "edit", "id": "a", "date": 4,
+                    "item": {"id": "a", "type": "paragraph", "text": "uno"}},
+                {"type": "move", "id": "b", "order": ["b", "a"]},
+                {"type": "remove", "id": "a", "date": 5},
+                {"type": "remove", "id": "missing", "date": 6}
+            ]
+        }))
+        .unwrap();
+        let story = |revision| texts(&page.revision(revision).unwrap()).join(" ");
+        assert_eq!(story(1), "one");
+        assert_eq!(story(2), "one two");
+        // an unknown action still counts as a revision, changing nothing
+        assert_eq!(story(3), "one two");
+        assert_eq!(story(4), "uno two");
+        assert_eq!(story(5), "two uno");
+        assert_eq!(story(6), "two");
+        assert_eq!(story(7), "two");
+        assert!(page.revision(0).is_err());
+        assert!(page.revision(8).is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_stores_leave_the_cached_page_alone() {
+        // a file where the wiki's directory should be can't hold any pages
+        let path = std::env::temp_dir().join(format!("terki-{}", random_id()));
+        std::fs::write(&path, "").unwrap();
+        let mut wiki = Wiki::new(PageStore::Local { path: path.clone() });
+        wiki.pages.insert("test".to_string(), page(&[("a", "one")]));
+        assert!(wiki.edit("test", 0, "uno".to_string()).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+        let page = wiki.page("test").await.unwrap();
+        assert_eq!(texts(page), vec!["one"]);
+        assert!(page.journal().is_empty());
     }
 }